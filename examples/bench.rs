@@ -2,7 +2,7 @@
 extern crate bencher;
 
 use bencher::{black_box, Bencher};
-use jsurl::{deserialize, serialize};
+use jsurl::{deserialize, serialize, serialize_reuse};
 
 fn bench_serialize(b: &mut Bencher) {
     let obj: serde_json::Value =
@@ -21,5 +21,184 @@ fn bench_deserialize(b: &mut Bencher) {
     });
 }
 
-benchmark_group!(benches, bench_serialize, bench_deserialize);
+fn large_object() -> serde_json::Value {
+    let people: Vec<serde_json::Value> = (0..1000)
+        .map(|i| {
+            serde_json::json!({
+                "id": i,
+                "name": format!("Person {}", i),
+                "tags": ["alpha", "beta", "gamma"],
+                "address": {"street": "123 Main St.", "zip": "12345", "verified": i % 2 == 0},
+            })
+        })
+        .collect();
+    serde_json::json!({ "people": people })
+}
+
+fn bench_serialize_large(b: &mut Bencher) {
+    let obj = large_object();
+    b.bytes = serialize(&obj).len() as u64;
+    b.iter(|| {
+        let s = serialize(&obj);
+        black_box(s);
+    });
+}
+
+fn bench_deserialize_large(b: &mut Bencher) {
+    let s = serialize(&large_object());
+    b.bytes = s.len() as u64;
+    b.iter(|| {
+        let obj = deserialize(&s).unwrap();
+        black_box(obj);
+    });
+}
+
+fn deserialize_wide_object(b: &mut Bencher, keys: usize) {
+    let obj: serde_json::Map<String, serde_json::Value> = (0..keys)
+        .map(|i| (format!("key_{}", i), serde_json::Value::from(i)))
+        .collect();
+    let s = serialize(&serde_json::Value::Object(obj));
+    b.bytes = s.len() as u64;
+    b.iter(|| {
+        let obj = deserialize(&s).unwrap();
+        black_box(obj);
+    });
+}
+
+fn bench_deserialize_wide_object(b: &mut Bencher) {
+    deserialize_wide_object(b, 10_000);
+}
+
+fn bench_deserialize_object_1000_keys(b: &mut Bencher) {
+    deserialize_wide_object(b, 1000);
+}
+
+fn bench_deserialize_nested_object(b: &mut Bencher) {
+    // 50 levels of objects, each with a few scalar siblings next to the nested one
+    let mut obj = serde_json::json!({"leaf": true});
+    for depth in 0..50 {
+        obj = serde_json::json!({"depth": depth, "name": "level", "child": obj, "tags": [1, 2]});
+    }
+    let s = serialize(&obj);
+    b.bytes = s.len() as u64;
+    b.iter(|| {
+        let obj = deserialize(&s).unwrap();
+        black_box(obj);
+    });
+}
+
+fn bench_deserialize_string_array(b: &mut Bencher) {
+    // 10000 strings without escapes, which are copied out of the input without decoding
+    let strings: Vec<String> = (0..10_000).map(|i| format!("item-{}", i)).collect();
+    let s = serialize(&serde_json::Value::from(strings));
+    b.bytes = s.len() as u64;
+    b.iter(|| {
+        let value = deserialize(&s).unwrap();
+        black_box(value);
+    });
+}
+
+fn bench_deserialize_long_string(b: &mut Bencher) {
+    // 64KB of text with an escaped space after every 31 plain chars
+    let s = format!(
+        "~'{}",
+        "lorem.ipsum.dolor.sit.amet.cons*20".repeat(64 * 1024 / 34)
+    );
+    b.bytes = s.len() as u64;
+    b.iter(|| {
+        let value = deserialize(&s).unwrap();
+        black_box(value);
+    });
+}
+
+fn bench_deserialize_escaped_strings(b: &mut Bencher) {
+    // many short strings with escapes, each decoded into its own `String`
+    let strings: Vec<String> = (0..10_000)
+        .map(|i| format!("item {} of 10000!", i))
+        .collect();
+    let s = serialize(&serde_json::Value::from(strings));
+    b.bytes = s.len() as u64;
+    b.iter(|| {
+        let value = deserialize(&s).unwrap();
+        black_box(value);
+    });
+}
+
+fn bench_deserialize_long_number(b: &mut Bencher) {
+    // a 1000-digit number, as with `arbitrary_precision`; a fraction so that it is also in
+    // range for an `f64` without it
+    let digits: String = (0..1000)
+        .map(|i| char::from(b'0' + (i % 9 + 1) as u8))
+        .collect();
+    let s = format!("~(~0.{}~0.{})", digits, digits);
+    b.bytes = s.len() as u64;
+    b.iter(|| {
+        let value = deserialize(&s).unwrap();
+        black_box(value);
+    });
+}
+
+fn bench_serialize_punctuation(b: &mut Bencher) {
+    let obj = serde_json::Value::String(" !\"#%&'()*+,/:;<=>?@[\\]^`{|}~".repeat(320));
+    b.bytes = serialize(&obj).len() as u64;
+    b.iter(|| {
+        let s = serialize(&obj);
+        black_box(s);
+    });
+}
+
+fn small_objects() -> Vec<serde_json::Value> {
+    (0..10_000)
+        .map(|i| serde_json::json!({"page": i, "sort": "name", "desc": i % 2 == 0}))
+        .collect()
+}
+
+fn bench_serialize_many_fresh(b: &mut Bencher) {
+    let objs = small_objects();
+    b.iter(|| {
+        for obj in objs.iter() {
+            let s = serialize(obj);
+            black_box(s);
+        }
+    });
+}
+
+fn bench_serialize_many_reuse(b: &mut Bencher) {
+    let objs = small_objects();
+    let mut buf = String::new();
+    b.iter(|| {
+        for obj in objs.iter() {
+            serialize_reuse(obj, &mut buf);
+            black_box(&buf);
+        }
+    });
+}
+
+fn bench_serialize_integer_array(b: &mut Bencher) {
+    let obj = serde_json::Value::from((0..100_000).map(|i| i * 37 - 50_000).collect::<Vec<i64>>());
+    b.bytes = serialize(&obj).len() as u64;
+    b.iter(|| {
+        let s = serialize(&obj);
+        black_box(s);
+    });
+}
+
+benchmark_group!(
+    benches,
+    bench_serialize,
+    bench_deserialize,
+    bench_serialize_large,
+    bench_deserialize_large,
+    bench_deserialize_wide_object,
+    bench_deserialize_nested_object,
+    bench_deserialize_object_1000_keys,
+    bench_deserialize_string_array,
+    bench_deserialize_long_string,
+    bench_deserialize_escaped_strings,
+    bench_deserialize_long_number,
+    bench_serialize_punctuation,
+    bench_serialize_many_fresh,
+    bench_serialize_many_reuse,
+    bench_serialize_integer_array
+);
 benchmark_main!(benches);
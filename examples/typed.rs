@@ -0,0 +1,33 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Report {
+    id: Uuid,
+    from: DateTime<Utc>,
+    until: Option<DateTime<Utc>>,
+    tags: Vec<String>,
+}
+
+fn main() {
+    let report = Report {
+        id: Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8),
+        from: Utc.with_ymd_and_hms(2024, 5, 1, 0, 0, 0).unwrap(),
+        until: None,
+        tags: vec!["q2".to_string(), "sales & ops".to_string()],
+    };
+
+    // Both types serialize as strings, so they are written like any other string: the `-`
+    // of the id and the date stay as they are, while the `:` of the time is escaped.
+    let state = jsurl::serialize_value(&report).unwrap();
+    println!("{}", state);
+    assert_eq!(
+        state,
+        "~(id~'67e55044-10b1-426f-9247-bb680e5fe0c8~from~'2024-05-01T00*3a00*3a00Z\
+         ~until~null~tags~(~'q2~'sales*20*26*20ops))"
+    );
+
+    let parsed: Report = jsurl::deserialize_into(&state).unwrap();
+    assert_eq!(parsed, report);
+}
@@ -0,0 +1,37 @@
+use serde_json::json;
+use url::Url;
+
+fn main() {
+    let filters = json!({"name": "O'Brien", "tags": ["a b", "c"]});
+
+    // Writing: `to_url_query_value` leaves nothing for `url` to percent-encode in the
+    // query, so the value appears in the URL exactly as given.
+    let mut url = Url::parse("https://example.com/search").unwrap();
+    url.set_query(Some(&format!(
+        "page=2&filters={}",
+        jsurl::to_url_query_value(&filters)
+    )));
+    println!("{}", url);
+
+    // Reading the raw query: split it into parameters and decode the value.
+    let raw = url
+        .query()
+        .unwrap()
+        .split('&')
+        .find_map(|param| param.strip_prefix("filters="))
+        .unwrap();
+    assert_eq!(jsurl::from_url_query_value(raw).unwrap(), filters);
+
+    // Reading with `query_pairs`, which has already percent-decoded the value.
+    let (_, value) = url.query_pairs().find(|(k, _)| k == "filters").unwrap();
+    assert_eq!(jsurl::from_url_query_value(&value).unwrap(), filters);
+
+    // `append_pair` form-encodes the plain jsurl string, which also round-trips through
+    // `query_pairs`, at the cost of a longer URL.
+    let mut url = Url::parse("https://example.com/search").unwrap();
+    url.query_pairs_mut()
+        .append_pair("filters", &jsurl::serialize(&filters));
+    println!("{}", url);
+    let (_, value) = url.query_pairs().find(|(k, _)| k == "filters").unwrap();
+    assert_eq!(jsurl::deserialize(&value).unwrap(), filters);
+}
@@ -0,0 +1,21 @@
+// Uses the JavaScript bindings of the `wasm` feature, built as described in the docs of
+// the `jsurl::wasm` module:
+//
+//   cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//   wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/jsurl.wasm
+import init, { stringify, parse, tryParse } from "../pkg/jsurl.js";
+
+await init();
+
+const s = stringify({ name: "John Doe", age: 42, children: ["Mary", "Bill"] });
+console.log(s); // ~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))
+
+console.log(parse(s)); // { name: 'John Doe', age: 42, children: [ 'Mary', 'Bill' ] }
+
+try {
+  parse("~(a~1");
+} catch (e) {
+  console.log(e.message); // error at char 5: unexpected end of input
+}
+
+console.log(tryParse("~(a~1", {})); // {}
@@ -0,0 +1,59 @@
+#![no_main]
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use libfuzzer_sys::fuzz_target;
+
+const MAX_DEPTH: usize = 8;
+
+/// A `serde_json::Value` generated from fuzzer input, with bounded nesting.
+#[derive(Debug)]
+struct Value(serde_json::Value);
+
+impl<'a> Arbitrary<'a> for Value {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        arbitrary_value(u, 0).map(Value)
+    }
+}
+
+fn arbitrary_value(u: &mut Unstructured, depth: usize) -> Result<serde_json::Value> {
+    let choices = if depth < MAX_DEPTH { 8 } else { 6 };
+    Ok(match u.int_in_range(0..=choices - 1)? {
+        0 => serde_json::Value::Null,
+        1 => serde_json::Value::Bool(u.arbitrary()?),
+        2 => serde_json::Value::from(u.arbitrary::<i64>()?),
+        3 => serde_json::Value::from(u.arbitrary::<u64>()?),
+        4 => {
+            // integral floats are known to come back as integers, see `roundtrip_check`
+            let n: f64 = u.arbitrary()?;
+            match serde_json::Number::from_f64(n) {
+                Some(n) if n.as_f64().is_some_and(|f| f.fract() != 0.0) => n.into(),
+                _ => serde_json::Value::Null,
+            }
+        }
+        5 => serde_json::Value::String(u.arbitrary()?),
+        6 => {
+            let len = u.arbitrary_len::<u8>()?;
+            let items = (0..len)
+                .map(|_| arbitrary_value(u, depth + 1))
+                .collect::<Result<_>>()?;
+            serde_json::Value::Array(items)
+        }
+        _ => {
+            let len = u.arbitrary_len::<u8>()?;
+            let mut map = serde_json::Map::new();
+            for _ in 0..len {
+                map.insert(u.arbitrary()?, arbitrary_value(u, depth + 1)?);
+            }
+            serde_json::Value::Object(map)
+        }
+    })
+}
+
+fuzz_target!(|value: Value| {
+    assert!(
+        jsurl::roundtrip_check(&value.0),
+        "round trip failed for {:?}: {:?}",
+        value.0,
+        jsurl::serialize(&value.0)
+    );
+});
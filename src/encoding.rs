@@ -0,0 +1,139 @@
+//! Escaping and unescaping of string content on its own.
+//!
+//! These functions work on the content of a single string or object key, without the `~'`
+//! prefix that marks a string value, and without any of the surrounding structure. Use
+//! [`crate::serialize`] and [`crate::deserialize`] for complete values.
+//!
+//! # Example
+//!
+//! ```rust
+//! use jsurl::encoding::{decode_str, encode_str};
+//!
+//! assert_eq!(encode_str("John Doe $5"), "John*20Doe*20!5");
+//! assert_eq!(decode_str("John*20Doe*20!5").unwrap(), "John Doe $5");
+//! ```
+
+use alloc::string::String;
+
+use crate::{decode, DeserializeError, ErrorKind, Reader, DEFAULT_DESERIALIZER};
+
+/// Escapes `s` the way [`crate::serialize`] escapes the content of strings and keys.
+pub fn encode_str(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    crate::Serializer::new()
+        .encode_string(s, &mut result)
+        .expect("writing to a String cannot fail");
+    result
+}
+
+/// Escapes as much of `s` as fits in `max_len` bytes, like [`encode_str`], and reports
+/// whether anything was cut off.
+///
+/// The output is cut between the escapes of two chars, so it never ends in the middle of an
+/// escape, nor between the two `**` escapes of a char outside the BMP. The prefix always
+/// decodes to a prefix of `s`, and may be shorter than `max_len` when the next char's escape
+/// would not fit.
+///
+/// # Example
+///
+/// ```rust
+/// use jsurl::encoding::encode_str_truncated;
+///
+/// assert_eq!(encode_str_truncated("ab cd", 5), (String::from("ab*20"), true));
+/// assert_eq!(encode_str_truncated("ab cd", 4), (String::from("ab"), true));
+/// assert_eq!(encode_str_truncated("\u{1f600}", 11), (String::new(), true));
+/// assert_eq!(encode_str_truncated("abc", 10), (String::from("abc"), false));
+/// ```
+pub fn encode_str_truncated(s: &str, max_len: usize) -> (String, bool) {
+    let serializer = crate::Serializer::new();
+    let mut result = String::with_capacity(s.len().min(max_len));
+    let mut escaped = String::new();
+    for ch in s.chars() {
+        escaped.clear();
+        serializer
+            .encode_string(ch.encode_utf8(&mut [0; 4]), &mut escaped)
+            .expect("writing to a String cannot fail");
+        if result.len() + escaped.len() > max_len {
+            return (result, true);
+        }
+        result.push_str(&escaped);
+    }
+    (result, false)
+}
+
+/// Unescapes string content produced by [`encode_str`].
+///
+/// Fails if an escape is malformed, or if `s` contains a `~` or `)`, which cannot appear
+/// unescaped inside a string.
+pub fn decode_str(s: &str) -> Result<String, DeserializeError> {
+    let mut chars = Reader::new(s, &DEFAULT_DESERIALIZER);
+    let result = decode(&mut chars)?.into_owned();
+    match chars.peek() {
+        Some(c) => Err(chars.error(ErrorKind::UnexpectedChar(c))),
+        None => Ok(result),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode_content() {
+        for (s, encoded) in [
+            ("", ""),
+            ("plain.key_1-2", "plain.key_1-2"),
+            ("~)*!$'(", "*7e*29*2a*21!*27*28"),
+            ("caf\u{e9} \u{1f600}", "caf*e9*20**d83d**de00"),
+        ] {
+            assert_eq!(encode_str(s), encoded);
+            assert_eq!(decode_str(encoded).unwrap(), s);
+        }
+        // uppercase escapes and unescaped chars are accepted
+        assert_eq!(decode_str("a b*2A(").unwrap(), "a b*(");
+    }
+
+    #[test]
+    fn encode_truncated_at_escape_boundaries() {
+        let s = "a \u{e9}\u{1f600}$~b";
+        let encoded = encode_str(s);
+        assert_eq!(encoded, "a*20*e9**d83d**de00!*7eb");
+        let mut previous = String::new();
+        for max_len in 0..encoded.len() + 2 {
+            let (prefix, truncated) = encode_str_truncated(s, max_len);
+            assert!(prefix.len() <= max_len);
+            assert!(encoded.starts_with(&prefix));
+            assert_eq!(truncated, prefix != encoded);
+            let decoded = decode_str(&prefix).unwrap();
+            assert!(s.starts_with(&decoded));
+            assert!(prefix.len() >= previous.len());
+            previous = prefix;
+        }
+        assert_eq!(encode_str_truncated(s, 4), (String::from("a*20"), true));
+        // both halves of the surrogate pair, or neither
+        assert_eq!(encode_str_truncated(s, 12), (String::from("a*20*e9"), true));
+        assert_eq!(
+            encode_str_truncated(s, 19),
+            (String::from("a*20*e9**d83d**de00"), true)
+        );
+        assert_eq!(encode_str_truncated("", 0), (String::new(), false));
+    }
+
+    #[test]
+    fn decode_rejects_structure() {
+        let err = decode_str("ab~c").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedChar('~'));
+        assert_eq!(err.position(), 2);
+        let err = decode_str("\u{e9})").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedChar(')'));
+        assert_eq!(err.position(), 1);
+        assert_eq!(
+            decode_str("x*zz").unwrap_err().kind(),
+            ErrorKind::InvalidEscape
+        );
+        assert_eq!(
+            decode_str("x*2").unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+}
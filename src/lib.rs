@@ -9,407 +9,6642 @@
 //! use serde_json::json;
 //!
 //! let obj = json!({
-//!     "name": "John Doe",
 //!     "age": 42,
-//!     "children": ["Mary", "Bill"]
+//!     "children": ["Mary", "Bill"],
+//!     "name": "John Doe"
 //! });
 //!
 //! let serialized = serialize(&obj);
-//! assert_eq!(serialized, "~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))");
+//! assert_eq!(serialized, "~(age~42~children~(~'Mary~'Bill)~name~'John*20Doe)");
 //!
-//! let deserialized = deserialize("~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))").unwrap();
+//! let deserialized = deserialize("~(age~42~children~(~'Mary~'Bill)~name~'John*20Doe)").unwrap();
 //! assert_eq!(deserialized, obj);
 //! ```
+//!
+//! # Features
+//!
+//! - `std` (enabled by default): implements `std::error::Error` for the error types, adds
+//!   `serialize_to_io` for writing to an `io::Write`, and enables serde_json's `preserve_order`
+//!   feature, so that objects keep their keys in input order.
+//! - `arbitrary_precision`: enables serde_json's `arbitrary_precision` feature, and writes
+//!   numbers that do not fit in an `i64` or `u64` exactly as they appear in the `Value`,
+//!   rather than going through `f64`.
+//! - `alloc`: allows the crate to be used in `no_std` environments that provide an allocator.
+//!   Disable default features and enable this one instead of `std`.
+//! - `serde_impls`: implements `Serialize` and `Deserialize` for `JsUrl` as its jsurl string,
+//!   so that a jsurl-encoded value can be embedded as a field of a larger serde structure.
+//! - `wasm`: exports `stringify`, `parse` and `tryParse` to JavaScript with wasm-bindgen, as a
+//!   drop-in replacement for the original JavaScript library. Implies `std`.
+//! - `ordered`: adds `deserialize_ordered`, which returns an `OrderedValue` whose objects keep
+//!   their keys in input order whether or not serde_json's `preserve_order` is enabled.
+//!   Implies `std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod encoding;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+
+/// Starts every value, and separates the entries of arrays and objects.
+pub const VALUE_MARKER: char = '~';
+/// Opens an array or object, directly after a [`VALUE_MARKER`].
+pub const OPEN: char = '(';
+/// Closes an array or object.
+pub const CLOSE: char = ')';
+/// Marks a value as a string, directly after a [`VALUE_MARKER`].
+pub const STRING_MARKER: char = '\'';
+/// Starts an escape inside a string or key: `*xx` for chars up to U+00FF, and `**xxxx` for
+/// each UTF-16 code unit of any other char. Serialization writes the hex digits in
+/// lowercase unless [`Serializer::uppercase_hex`] is set; deserialization accepts either
+/// case.
+pub const ESCAPE: char = '*';
+/// Stands for `$` inside a string or key.
+pub const DOLLAR: char = '!';
+
+/// Every char with a special meaning in the jsurl grammar.
+pub const RESERVED: [char; 6] = [VALUE_MARKER, OPEN, CLOSE, STRING_MARKER, ESCAPE, DOLLAR];
 
+/// Whether `c` is one of the [`RESERVED`] structural chars of the jsurl grammar.
+///
+/// Inside strings and keys, only `~`, `)`, `*`, and `!` must be escaped; `(` and `'` are
+/// only special directly after a `~`.
+///
+/// ```rust
+/// assert!(jsurl::is_reserved('~'));
+/// assert!(jsurl::is_reserved('\''));
+/// assert!(!jsurl::is_reserved('a'));
+/// assert!(!jsurl::is_reserved('$'));
+/// ```
+pub fn is_reserved(c: char) -> bool {
+    RESERVED.contains(&c)
+}
+
+/// Serializes a value to a jsurl string.
+///
+/// This is the infallible form of [`try_serialize`], which it delegates to.
 pub fn serialize(obj: &serde_json::Value) -> String {
-    let mut result = String::new();
-    serialize_helper(obj, &mut result);
-    result
+    Serializer::new().serialize(obj)
 }
 
 pub fn serialize_helper(obj: &serde_json::Value, output: &mut String) {
-    match obj {
-        serde_json::Value::Null => {
-            output.push_str("~null");
-        }
-        serde_json::Value::Bool(b) => {
-            output.push('~');
-            output.push_str(if *b { "true" } else { "false" });
-        }
-        serde_json::Value::Number(n) => {
-            if let Some(n) = n.as_i64() {
-                output.push('~');
-                output.push_str(&n.to_string());
-            } else if let Some(n) = n.as_f64() {
-                if n.is_finite() {
-                    output.push('~');
-                    output.push_str(&n.to_string());
+    Serializer::new().serialize_helper(obj, output);
+}
+
+/// Serializes a value into an existing writer, such as a `String` that already holds the
+/// start of a URL.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let mut url = String::from("https://example.com/?q=");
+/// jsurl::serialize_to_writer(&json!({"a": [1, 2]}), &mut url).unwrap();
+/// assert_eq!(url, "https://example.com/?q=~(a~(~1~2))");
+/// ```
+pub fn serialize_to_writer<W: core::fmt::Write + ?Sized>(
+    obj: &serde_json::Value,
+    output: &mut W,
+) -> core::fmt::Result {
+    Serializer::new().serialize_to_writer(obj, output)
+}
+
+/// Serializes a value as UTF-8 bytes into an `io::Write`, such as a file or socket, without
+/// building a `String` first.
+///
+/// The output is written in many small pieces, so an unbuffered writer should be wrapped
+/// in a `std::io::BufWriter`. Requires the `std` feature.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let mut bytes = Vec::new();
+/// jsurl::serialize_to_io(&json!({"a": [1, 2]}), &mut bytes).unwrap();
+/// assert_eq!(bytes, b"~(a~(~1~2))");
+/// ```
+#[cfg(feature = "std")]
+pub fn serialize_to_io<W: std::io::Write + ?Sized>(
+    obj: &serde_json::Value,
+    output: &mut W,
+) -> std::io::Result<()> {
+    Serializer::new().serialize_to_io(obj, output)
+}
+
+/// Serializes a value into `buf`, replacing its previous contents but keeping its
+/// allocation.
+///
+/// Reusing one buffer across many calls avoids allocating a fresh `String` for every value,
+/// for example when generating many URLs in a loop.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let mut buf = String::new();
+/// for i in 0..3 {
+///     jsurl::serialize_reuse(&json!({"page": i}), &mut buf);
+///     assert_eq!(buf, format!("~(page~{})", i));
+/// }
+/// ```
+pub fn serialize_reuse(obj: &serde_json::Value, buf: &mut String) {
+    Serializer::new().serialize_reuse(obj, buf);
+}
+
+/// Serializes any type implementing `serde::Serialize` to a jsurl string.
+///
+/// This is equivalent to calling `serde_json::to_value` followed by [`serialize`], and fails
+/// with [`Error::Json`] if the value cannot be represented as JSON.
+///
+/// Maps such as `BTreeMap` and `HashMap` are written as objects. Their keys go through
+/// serde_json's rules for JSON object keys: strings are used as-is, integers, floats, bools,
+/// and chars are converted to their string form (so `{1: "a"}` becomes `~(1~'a)`), and any
+/// other key, such as a tuple or a struct, is an error.
+///
+/// # Example
+///
+/// ```rust
+/// use std::collections::BTreeMap;
+///
+/// #[derive(serde::Serialize)]
+/// struct Config {
+///     name: String,
+///     retries: u32,
+/// }
+///
+/// let cfg = Config { name: "primary".to_string(), retries: 3 };
+/// assert_eq!(jsurl::serialize_value(&cfg).unwrap(), "~(name~'primary~retries~3)");
+///
+/// let mut filters = BTreeMap::new();
+/// filters.insert("min", 10);
+/// filters.insert("max", 20);
+/// assert_eq!(jsurl::serialize_value(&filters).unwrap(), "~(max~20~min~10)");
+/// ```
+pub fn serialize_value<T: serde::Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    Serializer::new().serialize_value(value)
+}
+
+/// Serializes a value in a canonical form, so that values which compare equal always produce
+/// byte-identical output. This makes the result suitable for use as a cache key.
+///
+/// The output is normalized as follows:
+///
+/// - Object keys are written in lexicographic order of their UTF-8 bytes, at every level of
+///   nesting, regardless of the order in which the `serde_json::Map` holds them.
+/// - Integers are written in plain decimal without a leading `+` or leading zeros, whether
+///   they are stored as an `i64` or a `u64`.
+/// - Floats are written the way JavaScript's `Number.prototype.toString` writes them, which
+///   is the shortest representation that reads back as the same `f64`. `-0.0` is written as
+///   `0`, since it compares equal to `0.0`.
+/// - With the `arbitrary_precision` feature, every number that is not an `i64` or `u64` is
+///   written as the text it holds instead. Two such numbers only compare equal when that text
+///   is identical, so no further normalization is needed.
+///
+/// Strings are escaped exactly as by [`serialize`], which always writes escapes with
+/// lowercase hex digits (`*2a`, never `*2A`), although both are accepted when reading.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let a = json!({"a": 1, "b": {"d": [true], "c": 2}});
+/// let b = json!({"b": {"c": 2, "d": [true]}, "a": 1});
+/// assert_eq!(jsurl::serialize_canonical(&a), "~(a~1~b~(c~2~d~(~true)))");
+/// assert_eq!(jsurl::serialize_canonical(&a), jsurl::serialize_canonical(&b));
+/// ```
+pub fn serialize_canonical(obj: &serde_json::Value) -> String {
+    Serializer::new().sort_keys(true).serialize(obj)
+}
+
+/// Compares two values by their [`serialize_canonical`] form, for sorting values in an
+/// order that does not depend on how their objects were built.
+///
+/// Values compare equal exactly when their canonical forms are identical. Otherwise they
+/// are ordered by the bytes of those forms, which is deterministic but not meaningful:
+/// `~10` sorts before `~9`, and `~'b` before `~(~)`.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+/// use std::cmp::Ordering;
+///
+/// let mut values = vec![json!({"b": 1, "a": 2}), json!(9), json!(10), json!({"a": 1})];
+/// values.sort_by(jsurl::compare_canonical);
+/// assert_eq!(values, [json!({"a": 1}), json!({"a": 2, "b": 1}), json!(10), json!(9)]);
+///
+/// let (a, b) = (json!({"a": 1, "b": 2}), json!({"b": 2, "a": 1}));
+/// assert_eq!(jsurl::compare_canonical(&a, &b), Ordering::Equal);
+/// ```
+pub fn compare_canonical(a: &serde_json::Value, b: &serde_json::Value) -> core::cmp::Ordering {
+    serialize_canonical(a).cmp(&serialize_canonical(b))
+}
+
+/// Computes the exact length in bytes of what [`serialize`] would produce, without building
+/// the string.
+///
+/// This is useful for checking a payload against a length budget, such as a URL length
+/// limit. Since the output is always ASCII, the length in bytes is also the length in chars.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let value = json!({"name": "John Doe", "tags": ["a", "b"]});
+/// assert_eq!(jsurl::serialized_len(&value), jsurl::serialize(&value).len());
+/// ```
+pub fn serialized_len(obj: &serde_json::Value) -> usize {
+    Serializer::new().serialized_len(obj)
+}
+
+/// Serializes a value, returning an error instead of panicking if it cannot be represented.
+///
+/// [`serialize`] only fails on `serde_json::Number`s that are neither integers nor floats,
+/// which the current version of serde_json never produces. Code that must not panic under
+/// any circumstances, such as a server handling untrusted `Value`s, can use this instead.
+pub fn try_serialize(obj: &serde_json::Value) -> Result<String, SerializeError> {
+    Serializer::new().try_serialize(obj)
+}
+
+/// Serializes a value without the `~` that every jsurl value starts with, for contexts
+/// that already imply a jsurl value. This saves one character.
+///
+/// The output is not standard jsurl: read it back with [`deserialize_compact`], since
+/// [`deserialize`] and the JavaScript implementation reject it.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// assert_eq!(jsurl::serialize_compact(&json!("hello")), "'hello");
+/// assert_eq!(jsurl::serialize_compact(&json!({"a": 1})), "(a~1)");
+/// assert_eq!(jsurl::deserialize_compact("(a~1)").unwrap(), json!({"a": 1}));
+/// ```
+pub fn serialize_compact(obj: &serde_json::Value) -> String {
+    Serializer::new().serialize_compact(obj)
+}
+
+/// An error returned when a value cannot be serialized.
+///
+/// More variants may be added in minor releases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SerializeError {
+    /// A number was neither an integer nor a float.
+    UnsupportedNumber,
+    /// The output writer returned an error.
+    Write,
+}
+
+impl core::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SerializeError::UnsupportedNumber => f.write_str("unsupported number type"),
+            SerializeError::Write => f.write_str("failed to write output"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SerializeError {}
+
+impl From<core::fmt::Error> for SerializeError {
+    fn from(_: core::fmt::Error) -> Self {
+        SerializeError::Write
+    }
+}
+
+/// A configurable serializer.
+///
+/// The free function [`serialize`] is equivalent to `Serializer::new().serialize(..)`.
+///
+/// Object keys are escaped like strings, but written without the `~'` prefix. If the first
+/// key of an object is empty, it is preceded by an extra `~`, so `{"": 1}` is written as
+/// `~(~~1)`: the form `~(~1)` that the JavaScript implementation produces is read back as
+/// the array `[1]`. Note that the JavaScript implementation cannot read `~(~~1)`; see
+/// [`Serializer::mark_empty_first_key`].
+///
+/// # Example
+///
+/// With `sort_keys(true)`, object keys are always written in lexicographic order,
+/// regardless of how the underlying `serde_json::Map` iterates:
+///
+/// ```rust
+/// use jsurl::Serializer;
+/// use serde_json::json;
+///
+/// let serializer = Serializer::new().sort_keys(true);
+/// assert_eq!(serializer.serialize(&json!({"b": 1, "a": 2})), "~(a~2~b~1)");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Serializer {
+    sort_keys: bool,
+    readable: bool,
+    safe_chars: String,
+    escape_dollar_as_hex: bool,
+    preserve_floats: bool,
+    url_context: Option<UrlContext>,
+    skip_nulls: bool,
+    space_as_plus: bool,
+    uppercase_hex: bool,
+    null_as: NullRepr,
+    separator: char,
+    mark_empty_first_key: bool,
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serializer {
+    /// Creates a serializer with every option at its default, which matches [`serialize`].
+    ///
+    /// Since this is a `const fn`, the result can be stored in a `static`.
+    pub const fn new() -> Self {
+        Serializer {
+            sort_keys: false,
+            readable: false,
+            safe_chars: String::new(),
+            escape_dollar_as_hex: false,
+            preserve_floats: false,
+            url_context: None,
+            skip_nulls: false,
+            space_as_plus: false,
+            uppercase_hex: false,
+            null_as: NullRepr::Null,
+            separator: VALUE_MARKER,
+            mark_empty_first_key: true,
+        }
+    }
+
+    /// Write object keys in sorted order instead of the map's iteration order. Disabled by
+    /// default.
+    pub fn sort_keys(mut self, sort_keys: bool) -> Self {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// Leave every printable character unescaped in strings and keys, except for the few
+    /// that the decoder treats specially (`~`, `)`, `*`, `!`, and `$`).
+    ///
+    /// This is meant for debugging output only: the result may contain spaces, `&`, `#`,
+    /// non-ASCII text, and other characters that are not safe to put directly into a URL.
+    /// It can still be read back with [`deserialize`]. Disabled by default.
+    ///
+    /// ```rust
+    /// use jsurl::Serializer;
+    /// use serde_json::json;
+    ///
+    /// let value = json!({"greeting": "hello world, caf\u{e9}!"});
+    /// assert_eq!(
+    ///     Serializer::new().readable(true).serialize(&value),
+    ///     "~(greeting~'hello world, caf\u{e9}*21)"
+    /// );
+    /// ```
+    pub fn readable(mut self, readable: bool) -> Self {
+        self.readable = readable;
+        self
+    }
+
+    /// Leave the given characters unescaped in strings and keys, in addition to ASCII
+    /// alphanumerics and `.`, `_` and `-`. Calling this again adds to the set.
+    ///
+    /// Only characters that are safe in the URL component the output ends up in should be
+    /// added, such as `@` or `:` in a fragment. The structural characters are handled as
+    /// follows:
+    ///
+    /// - `~`, `)`, `*` and `!` are always escaped, even if they are given here, since the
+    ///   decoder treats them specially inside strings.
+    /// - `$` is always written as `!`, or as `*24` with [`Serializer::escape_dollar_as_hex`].
+    /// - `(` and `'` are only special directly after a `~`, which is never the case inside a
+    ///   string, so they can be left unescaped.
+    ///
+    /// The result can be read back with [`deserialize`], which accepts any unescaped
+    /// characters inside strings. The set is empty by default.
+    ///
+    /// ```rust
+    /// use jsurl::Serializer;
+    /// use serde_json::json;
+    ///
+    /// let value = json!({"email": "me@example.com", "time": "12:30"});
+    /// let serializer = Serializer::new().safe_chars("@:");
+    /// assert_eq!(serializer.serialize(&value), "~(email~'me@example.com~time~'12:30)");
+    /// ```
+    pub fn safe_chars(mut self, chars: &str) -> Self {
+        self.safe_chars.push_str(chars);
+        self
+    }
+
+    /// Leave the characters that are safe in the given URL component unescaped in strings
+    /// and keys, for shorter output. See [`UrlContext`] for the characters of each context.
+    ///
+    /// This composes with [`Serializer::safe_chars`], and the result can be read back with
+    /// [`deserialize`] whichever context was used. By default no context is set, and only
+    /// the characters that are safe anywhere in a URL are left unescaped.
+    ///
+    /// ```rust
+    /// use jsurl::{Serializer, UrlContext};
+    /// use serde_json::json;
+    ///
+    /// let value = json!({"q": "a=b&c/d"});
+    /// assert_eq!(jsurl::serialize(&value), "~(q~'a*3db*26c*2fd)");
+    /// let query = Serializer::new().url_context(UrlContext::Query);
+    /// assert_eq!(query.serialize(&value), "~(q~'a*3db*26c/d)");
+    /// let fragment = Serializer::new().url_context(UrlContext::Fragment);
+    /// assert_eq!(fragment.serialize(&value), "~(q~'a=b&c/d)");
+    /// ```
+    pub fn url_context(mut self, url_context: UrlContext) -> Self {
+        self.url_context = Some(url_context);
+        self
+    }
+
+    /// Write `$` as the generic escape `*24` instead of the shorthand `!`.
+    ///
+    /// This makes the output slightly longer, but keeps `!` out of it entirely, for systems
+    /// that treat `!` specially in URLs. The decoder reads both forms as `$`, so the output
+    /// of either mode can be read back with [`deserialize`]. Disabled by default.
+    ///
+    /// ```rust
+    /// use jsurl::Serializer;
+    /// use serde_json::json;
+    ///
+    /// let value = json!({"$ref": "$1"});
+    /// assert_eq!(Serializer::new().serialize(&value), "~(!ref~'!1)");
+    /// assert_eq!(
+    ///     Serializer::new().escape_dollar_as_hex(true).serialize(&value),
+    ///     "~(*24ref~'*241)"
+    /// );
+    /// ```
+    pub fn escape_dollar_as_hex(mut self, escape_dollar_as_hex: bool) -> Self {
+        self.escape_dollar_as_hex = escape_dollar_as_hex;
+        self
+    }
+
+    /// Write floats with an integral value with a trailing `.0`, so that they are read back
+    /// as floats rather than integers.
+    ///
+    /// By default floats are written the way JavaScript writes numbers, which has no
+    /// separate integer type: `3.0` is written as `~3`, and [`deserialize`] then returns it
+    /// as the integer `3`. With this option it is written as `~3.0` instead, which
+    /// [`deserialize`] returns as a float. Floats written with an exponent, such as `1e+21`,
+    /// are already read back as floats and are left alone. The JavaScript implementation
+    /// reads both forms as the same number.
+    ///
+    /// This has no effect with the `arbitrary_precision` feature, where a float is written
+    /// as the text it holds, and so keeps its decimal point. Disabled by default.
+    ///
+    /// ```rust
+    /// use jsurl::Serializer;
+    /// use serde_json::json;
+    ///
+    /// let value = json!([3.0, 2.5, 4]);
+    /// let serialized = Serializer::new().preserve_floats(true).serialize(&value);
+    /// assert_eq!(serialized, "~(~3.0~2.5~4)");
+    /// assert_eq!(jsurl::deserialize(&serialized).unwrap(), value);
+    /// ```
+    pub fn preserve_floats(mut self, preserve_floats: bool) -> Self {
+        self.preserve_floats = preserve_floats;
+        self
+    }
+
+    /// Leave out object entries whose value is `null`, for shorter output.
+    ///
+    /// This is lossy: the key is gone from the output, so [`deserialize`] cannot tell it
+    /// apart from a key that was never there. It suits structs with `Option` fields whose
+    /// `None` means "not set". Nulls inside arrays are kept, since dropping them would shift
+    /// the elements after them. Disabled by default.
+    ///
+    /// ```rust
+    /// use jsurl::Serializer;
+    /// use serde_json::json;
+    ///
+    /// let value = json!({"filter": {"tag": null}, "ids": [null], "page": 2, "sort": null});
+    /// assert_eq!(
+    ///     Serializer::new().skip_nulls(true).serialize(&value),
+    ///     "~(filter~()~ids~(~null)~page~2)"
+    /// );
+    /// ```
+    pub fn skip_nulls(mut self, skip_nulls: bool) -> Self {
+        self.skip_nulls = skip_nulls;
+        self
+    }
+
+    /// Write spaces in strings and keys as `+` instead of `*20`, as form-encoded query
+    /// strings do.
+    ///
+    /// A literal `+` is then always written as `*2b`, even if [`Serializer::safe_chars`],
+    /// [`Serializer::url_context`] or [`Serializer::readable`] would leave it unescaped, so
+    /// that it cannot be mistaken for a space. The output must be read back with
+    /// [`Deserializer::plus_as_space`]: [`deserialize`] and the JavaScript implementation
+    /// read the `+` as a literal `+`. Disabled by default.
+    ///
+    /// ```rust
+    /// use jsurl::{Deserializer, Serializer};
+    /// use serde_json::json;
+    ///
+    /// let value = json!({"q": "1 + 1"});
+    /// let serialized = Serializer::new().space_as_plus(true).serialize(&value);
+    /// assert_eq!(serialized, "~(q~'1+*2b+1)");
+    /// let deserializer = Deserializer::new().plus_as_space(true);
+    /// assert_eq!(deserializer.deserialize(&serialized).unwrap(), value);
+    /// ```
+    pub fn space_as_plus(mut self, space_as_plus: bool) -> Self {
+        self.space_as_plus = space_as_plus;
+        self
+    }
+
+    /// Write the hex digits of escapes in uppercase, as in `*2A` and `**D83D`, to match
+    /// other implementations that do. Disabled by default.
+    ///
+    /// [`deserialize`] reads either case, so the output can be read back as usual.
+    ///
+    /// ```rust
+    /// use jsurl::Serializer;
+    /// use serde_json::json;
+    ///
+    /// let value = json!("*caf\u{e9}*");
+    /// assert_eq!(jsurl::serialize(&value), "~'*2acaf*e9*2a");
+    /// assert_eq!(
+    ///     Serializer::new().uppercase_hex(true).serialize(&value),
+    ///     "~'*2Acaf*E9*2A"
+    /// );
+    /// ```
+    pub fn uppercase_hex(mut self, uppercase_hex: bool) -> Self {
+        self.uppercase_hex = uppercase_hex;
+        self
+    }
+
+    /// How to write `null`, for consumers that cannot read `~null`. Defaults to
+    /// [`NullRepr::Null`].
+    ///
+    /// Any other choice is lossy, since the result reads back as something else. To leave
+    /// out object entries whose value is `null` instead, see [`Serializer::skip_nulls`].
+    ///
+    /// ```rust
+    /// use jsurl::{NullRepr, Serializer};
+    /// use serde_json::json;
+    ///
+    /// let value = json!({"a": null, "b": [null]});
+    /// let serializer = Serializer::new().null_as(NullRepr::EmptyString);
+    /// assert_eq!(serializer.serialize(&value), "~(a~'~b~(~'))");
+    /// ```
+    pub fn null_as(mut self, null_as: NullRepr) -> Self {
+        self.null_as = null_as;
+        self
+    }
+
+    /// Write `separator` in place of every [`VALUE_MARKER`] `~`, for systems along the way
+    /// that mangle `~`. `~` is the default.
+    ///
+    /// The output is then a different dialect, which [`deserialize`] and other jsurl
+    /// implementations cannot read: only a [`Deserializer`] with the same
+    /// [`Deserializer::separator`] does. The separator is escaped wherever it occurs in
+    /// strings and keys, and so is `~`.
+    ///
+    /// `_` is a good choice, since like `~` it never needs percent-encoding in a URL. Any
+    /// other ASCII punctuation works too, except for the chars that already mean something
+    /// in jsurl (`(`, `)`, `'`, `*` and `!`), in numbers (`-`, `.` and `+`) or in
+    /// percent-encoding (`%`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `separator` is not one of the chars allowed above.
+    ///
+    /// ```rust
+    /// use jsurl::{Deserializer, Serializer};
+    /// use serde_json::json;
+    ///
+    /// let value = json!({"a_b": [1, "x~y"]});
+    /// let serialized = Serializer::new().separator('_').serialize(&value);
+    /// assert_eq!(serialized, "_(a*5fb_(_1_'x*7ey))");
+    /// assert!(jsurl::deserialize(&serialized).is_err());
+    /// let deserializer = Deserializer::new().separator('_');
+    /// assert_eq!(deserializer.deserialize(&serialized).unwrap(), value);
+    /// ```
+    pub fn separator(mut self, separator: char) -> Self {
+        assert_separator(separator);
+        self.separator = separator;
+        self
+    }
+
+    /// Precede an empty first key of an object with an extra `~`, so that `{"": 1}` is
+    /// written as `~(~~1)`. Enabled by default.
+    ///
+    /// Disabling this writes `~(~1)` as the JavaScript implementation does, which can read
+    /// it back, but which [`deserialize`] reads as the array `[1]`; with more keys, as in
+    /// `~(~1~a~2)`, it is rejected. Only disable it when the output is read by another
+    /// implementation.
+    ///
+    /// ```rust
+    /// use jsurl::Serializer;
+    /// use serde_json::json;
+    ///
+    /// let value = json!({"": 1});
+    /// assert_eq!(Serializer::new().serialize(&value), "~(~~1)");
+    /// let reference = Serializer::new().mark_empty_first_key(false);
+    /// assert_eq!(reference.serialize(&value), "~(~1)");
+    /// assert_eq!(jsurl::deserialize("~(~1)").unwrap(), json!([1]));
+    /// ```
+    pub fn mark_empty_first_key(mut self, mark_empty_first_key: bool) -> Self {
+        self.mark_empty_first_key = mark_empty_first_key;
+        self
+    }
+
+    pub fn serialize(&self, obj: &serde_json::Value) -> String {
+        // every `serde_json::Number` is an integer or a float, so this never fails
+        self.try_serialize(obj)
+            .expect("serializing to a String cannot fail")
+    }
+
+    /// Serializes a value without its leading `~`. See [`serialize_compact`].
+    pub fn serialize_compact(&self, obj: &serde_json::Value) -> String {
+        let mut result = self.serialize(obj);
+        // every value starts with `~`
+        result.remove(0);
+        result
+    }
+
+    /// Serializes a value into `buf`, replacing its previous contents. See
+    /// [`serialize_reuse`].
+    pub fn serialize_reuse(&self, obj: &serde_json::Value, buf: &mut String) {
+        buf.clear();
+        buf.reserve(estimate_len(obj));
+        self.serialize_helper(obj, buf);
+    }
+
+    /// Serializes a value, returning an error instead of panicking. See [`try_serialize`].
+    pub fn try_serialize(&self, obj: &serde_json::Value) -> Result<String, SerializeError> {
+        let mut result = String::with_capacity(estimate_len(obj));
+        self.write(obj, &mut result)?;
+        Ok(result)
+    }
+
+    /// Computes the exact length of what [`Serializer::serialize`] would produce. See
+    /// [`serialized_len`].
+    ///
+    /// With [`Serializer::readable`] or [`Serializer::safe_chars`], the output may contain
+    /// non-ASCII chars, and the length is counted in bytes.
+    pub fn serialized_len(&self, obj: &serde_json::Value) -> usize {
+        let mut counter = LenCounter(0);
+        self.write(obj, &mut counter)
+            .expect("counting output cannot fail");
+        counter.0
+    }
+
+    /// Serializes a value into an existing writer. See [`serialize_to_writer`].
+    pub fn serialize_to_writer<W: core::fmt::Write + ?Sized>(
+        &self,
+        obj: &serde_json::Value,
+        output: &mut W,
+    ) -> core::fmt::Result {
+        self.write(obj, output).map_err(|_| core::fmt::Error)
+    }
+
+    /// Serializes a value as UTF-8 bytes into an `io::Write`. See [`serialize_to_io`].
+    #[cfg(feature = "std")]
+    pub fn serialize_to_io<W: std::io::Write + ?Sized>(
+        &self,
+        obj: &serde_json::Value,
+        output: &mut W,
+    ) -> std::io::Result<()> {
+        let mut writer = IoWriter {
+            inner: output,
+            error: None,
+        };
+        match self.write(obj, &mut writer) {
+            Ok(()) => Ok(()),
+            Err(SerializeError::Write) => {
+                Err(writer.error.expect("a failed write records its io error"))
+            }
+            Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    /// Serializes any type implementing `serde::Serialize`. See [`serialize_value`].
+    pub fn serialize_value<T: serde::Serialize + ?Sized>(
+        &self,
+        value: &T,
+    ) -> Result<String, Error> {
+        Ok(self.serialize(&serde_json::to_value(value)?))
+    }
+
+    fn serialize_helper(&self, obj: &serde_json::Value, output: &mut String) {
+        // writing to a String cannot fail, and every `serde_json::Number` is an integer or
+        // a float, so `UnsupportedNumber` is never returned
+        self.write(obj, output)
+            .expect("serializing to a String cannot fail");
+    }
+
+    fn write<W: core::fmt::Write + ?Sized>(
+        &self,
+        obj: &serde_json::Value,
+        output: &mut W,
+    ) -> Result<(), SerializeError> {
+        if self.separator == VALUE_MARKER {
+            return self.write_value(obj, output);
+        }
+        let mut writer = SeparatorWriter {
+            inner: output,
+            separator: self.separator,
+        };
+        self.write_value(obj, &mut writer)
+    }
+
+    fn write_value<W: core::fmt::Write + ?Sized>(
+        &self,
+        obj: &serde_json::Value,
+        output: &mut W,
+    ) -> Result<(), SerializeError> {
+        match obj {
+            serde_json::Value::Null => output.write_str(match self.null_as {
+                NullRepr::Null => "~null",
+                NullRepr::EmptyString => "~'",
+            })?,
+            serde_json::Value::Bool(b) => output.write_str(if *b { "~true" } else { "~false" })?,
+            serde_json::Value::Number(n) => {
+                if let Some(n) = n.as_i64() {
+                    write_integer_value(n, output)?;
+                } else if let Some(n) = n.as_u64() {
+                    write!(output, "~{}", n)?;
+                } else if cfg!(feature = "arbitrary_precision") {
+                    // the number holds its original decimal text, which is valid jsurl as-is
+                    write!(output, "~{}", n)?;
+                } else if let Some(n) = n.as_f64() {
+                    write_float_value(n, output)?;
+                    // below 1e21 an integral float is written without an exponent, so it
+                    // would otherwise look like an integer
+                    if self.preserve_floats && n % 1.0 == 0.0 && n.abs() < 1e21 {
+                        output.write_str(".0")?;
+                    }
+                } else {
+                    return Err(SerializeError::UnsupportedNumber);
+                }
+            }
+            serde_json::Value::String(s) => {
+                output.write_str("~'")?;
+                self.encode_string(s, output)?;
+            }
+            serde_json::Value::Array(a) => {
+                output.write_str("~(")?;
+                if a.is_empty() {
+                    output.write_char('~')?;
+                } else if a.iter().all(serde_json::Value::is_i64) {
+                    // arrays of integers skip the dispatch on every element
+                    for n in a.iter().filter_map(serde_json::Value::as_i64) {
+                        write_integer_value(n, output)?;
+                    }
                 } else {
-                    // https://github.com/Sage/jsurl/blob/b1e244d145bb440f776d8fec673cc743c42c5cbc/lib/jsurl.js#L42
-                    output.push_str("~null");
+                    for v in a.iter() {
+                        self.write_value(v, output)?;
+                    }
+                }
+                output.write_char(')')?;
+            }
+            serde_json::Value::Object(o) => {
+                output.write_str("~(")?;
+                if self.sort_keys {
+                    let mut entries: Vec<_> = o.iter().collect();
+                    entries.sort_by_key(|(k, _)| *k);
+                    self.write_entries(entries.into_iter(), output)?;
+                } else {
+                    self.write_entries(o.iter(), output)?;
                 }
+                output.write_char(')')?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_entries<'a, W: core::fmt::Write + ?Sized>(
+        &self,
+        entries: impl Iterator<Item = (&'a String, &'a serde_json::Value)>,
+        output: &mut W,
+    ) -> Result<(), SerializeError> {
+        let entries = entries.filter(|(_, v)| !(self.skip_nulls && v.is_null()));
+        for (i, (k, v)) in entries.enumerate() {
+            // a first key that is empty gets a `~` too, because `~(~1)` would be read as an
+            // array, while `~(~~1)` is unambiguous
+            if i > 0 || (self.mark_empty_first_key && k.is_empty()) {
+                output.write_char('~')?;
+            }
+            self.encode_string(k, output)?;
+            self.write_value(v, output)?;
+        }
+        Ok(())
+    }
+
+    fn encode_string<W: core::fmt::Write + ?Sized>(
+        &self,
+        s: &str,
+        output: &mut W,
+    ) -> core::fmt::Result {
+        for ch in s.chars() {
+            if ch == '$' {
+                output.write_str(if self.escape_dollar_as_hex {
+                    "*24"
+                } else {
+                    "!"
+                })?;
+            } else if self.space_as_plus && ch == ' ' {
+                output.write_char('+')?;
+            } else if self.space_as_plus && ch == '+' {
+                output.write_str(if self.uppercase_hex { "*2B" } else { "*2b" })?;
+            } else if self.is_unescaped(ch) {
+                output.write_char(ch)?;
             } else {
-                panic!("Unexpected number type")
+                let code = ch as u32;
+                if code < 0x100 {
+                    output.write_char('*')?;
+                    write_hex(code, 2, self.uppercase_hex, output)?;
+                } else {
+                    // characters outside the BMP are written as a UTF-16 surrogate pair,
+                    // matching the JS implementation which escapes one code unit at a time
+                    let mut units = [0u16; 2];
+                    for unit in ch.encode_utf16(&mut units) {
+                        output.write_str("**")?;
+                        write_hex(*unit as u32, 4, self.uppercase_hex, output)?;
+                    }
+                }
             }
         }
-        serde_json::Value::String(s) => {
-            output.push_str("~'");
-            encode_string(s, output);
+        Ok(())
+    }
+
+    /// Whether `ch` can be written to string content as-is.
+    fn is_unescaped(&self, ch: char) -> bool {
+        if ch == self.separator {
+            return false;
+        }
+        if ch.is_ascii_alphanumeric() || ch == '.' || ch == '_' || ch == '-' {
+            return true;
+        }
+        // the decoder only gives special meaning to these characters inside strings
+        if matches!(ch, VALUE_MARKER | CLOSE | ESCAPE | DOLLAR) {
+            return false;
+        }
+        (self.readable && !ch.is_control())
+            || self.safe_chars.contains(ch)
+            || self
+                .url_context
+                .is_some_and(|context| context.safe_chars().contains(ch))
+    }
+}
+
+/// How [`Serializer::null_as`] writes `null`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum NullRepr {
+    /// `~null`, which is read back as `null`.
+    #[default]
+    Null,
+    /// `~'`, which is read back as the empty string.
+    EmptyString,
+}
+
+/// The URL component that serialized output is meant for, set with
+/// [`Serializer::url_context`].
+///
+/// By default only ASCII alphanumerics and `.`, `_` and `-` are left unescaped, which is
+/// safe anywhere in a URL. Each context additionally leaves these characters unescaped:
+///
+/// | Context       | `'` `(` `,` `:` `@` | `;` | `&` `=` `+` | `/` `?` |
+/// |---------------|:-------------------:|:---:|:-----------:|:-------:|
+/// | `Query`       | yes                 |     |             | yes     |
+/// | `Fragment`    | yes                 | yes | yes         | yes     |
+/// | `PathSegment` | yes                 | yes | yes         |         |
+///
+/// All of them are allowed unescaped in that component by RFC 3986. In a query, `&`, `=`
+/// and `;` are still escaped since they commonly separate parameters, and `+` since form
+/// decoding reads it as a space. In a path segment, `/` and `?` would end the segment.
+///
+/// `~`, `)`, `*`, `!` and `$` are escaped in every context, as described in
+/// [`Serializer::safe_chars`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UrlContext {
+    /// The value of a query string parameter, after `?` and before `#`.
+    Query,
+    /// The fragment, after `#`.
+    Fragment,
+    /// A single segment of the path, between two `/`.
+    PathSegment,
+}
+
+impl UrlContext {
+    /// The characters left unescaped in this context, in addition to the default ones.
+    fn safe_chars(self) -> &'static str {
+        match self {
+            UrlContext::Query => "'(,:@/?",
+            UrlContext::Fragment => "'(,:@;&=+/?",
+            UrlContext::PathSegment => "'(,:@;&=+",
+        }
+    }
+}
+
+/// Cheaply estimates the length of the serialized output, so that the output buffer
+/// can be allocated once up front. Strings are assumed to need no escaping, so this
+/// is usually a slight underestimate.
+fn estimate_len(obj: &serde_json::Value) -> usize {
+    match obj {
+        serde_json::Value::Null => 5,
+        serde_json::Value::Bool(_) => 6,
+        serde_json::Value::Number(_) => 8,
+        serde_json::Value::String(s) => 2 + s.len(),
+        serde_json::Value::Array(a) => 3 + a.iter().map(estimate_len).sum::<usize>(),
+        serde_json::Value::Object(o) => {
+            3 + o
+                .iter()
+                .map(|(k, v)| 1 + k.len() + estimate_len(v))
+                .sum::<usize>()
+        }
+    }
+}
+
+/// A writer that only counts the bytes written to it.
+struct LenCounter(usize);
+
+impl core::fmt::Write for LenCounter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// Replaces every `~` written to it with the [`Serializer::separator`]. Strings and keys
+/// never contain a literal `~`, so each one is structural.
+struct SeparatorWriter<'a, W: core::fmt::Write + ?Sized> {
+    inner: &'a mut W,
+    separator: char,
+}
+
+impl<W: core::fmt::Write + ?Sized> core::fmt::Write for SeparatorWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let mut parts = s.split(VALUE_MARKER);
+        if let Some(first) = parts.next() {
+            self.inner.write_str(first)?;
+        }
+        for part in parts {
+            self.inner.write_char(self.separator)?;
+            self.inner.write_str(part)?;
+        }
+        Ok(())
+    }
+}
+
+/// Panics unless `separator` may stand in for `~`, as described at
+/// [`Serializer::separator`].
+fn assert_separator(separator: char) {
+    assert!(
+        separator.is_ascii_punctuation()
+            && !matches!(
+                separator,
+                '(' | ')' | '\'' | '*' | '!' | '-' | '.' | '+' | '%'
+            ),
+        "{:?} cannot be used as a jsurl separator",
+        separator
+    );
+}
+
+/// Adapts an `io::Write` to the `fmt::Write` that the serializer writes to, keeping the
+/// io error that `fmt::Error` has no room for.
+#[cfg(feature = "std")]
+struct IoWriter<'a, W: std::io::Write + ?Sized> {
+    inner: &'a mut W,
+    error: Option<std::io::Error>,
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write + ?Sized> core::fmt::Write for IoWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            core::fmt::Error
+        })
+    }
+}
+
+/// A fixed-size buffer for formatting a number without allocating.
+struct NumberBuf {
+    buf: [u8; 32],
+    len: usize,
+}
+
+impl NumberBuf {
+    fn new() -> Self {
+        NumberBuf {
+            buf: [0; 32],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // only ASCII is ever written into the buffer
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+}
+
+impl core::fmt::Write for NumberBuf {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(core::fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Writes an `i64` as a complete value, formatting the digits by hand since `write!` is
+/// comparatively slow for large arrays of integers.
+fn write_integer_value<W: core::fmt::Write + ?Sized>(n: i64, output: &mut W) -> core::fmt::Result {
+    // '~', a sign, and up to 19 digits
+    let mut buf = [0u8; 21];
+    let mut start = buf.len();
+    let mut rest = n.unsigned_abs();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        start -= 1;
+        buf[start] = b'-';
+    }
+    start -= 1;
+    buf[start] = b'~';
+    output.write_str(core::str::from_utf8(&buf[start..]).map_err(|_| core::fmt::Error)?)
+}
+
+/// Writes an `f64` as a complete value. Non-finite numbers are written as `~null`, like the
+/// reference implementation does (and like `JSON.stringify`). A `serde_json::Value` cannot
+/// normally hold them, but this keeps the output valid should one ever get through.
+fn write_float_value<W: core::fmt::Write + ?Sized>(n: f64, output: &mut W) -> core::fmt::Result {
+    if n.is_finite() {
+        output.write_char('~')?;
+        write_f64(n, output)
+    } else {
+        // https://github.com/Sage/jsurl/blob/b1e244d145bb440f776d8fec673cc743c42c5cbc/lib/jsurl.js#L42
+        output.write_str("~null")
+    }
+}
+
+/// Writes a finite `f64` the way JavaScript's `Number.prototype.toString()` does, so that
+/// output matches the reference implementation byte for byte. In particular `-0.0` is
+/// written as `0`, so it produces the same output as `0.0`, which it compares equal to.
+///
+/// Rust's `{:e}` formatting already produces the shortest digits that round-trip, which is
+/// also what JS uses; only the placement of the decimal point and exponent differ.
+fn write_f64<W: core::fmt::Write + ?Sized>(n: f64, output: &mut W) -> core::fmt::Result {
+    if n == 0.0 {
+        // covers -0, which JS prints as "0"
+        return output.write_char('0');
+    }
+    let mut buf = NumberBuf::new();
+    write!(buf, "{:e}", n.abs())?;
+    let (mantissa, exponent) = buf.as_str().split_once('e').ok_or(core::fmt::Error)?;
+    let exponent: i32 = exponent.parse().map_err(|_| core::fmt::Error)?;
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let mut digits = NumberBuf::new();
+    digits.write_str(int_part)?;
+    digits.write_str(frac_part)?;
+    let digits = digits.as_str();
+
+    if n < 0.0 {
+        output.write_char('-')?;
+    }
+    // `k` significant digits with the decimal point after the `n`th, as in the
+    // Number::toString algorithm of the ECMAScript spec
+    let k = digits.len() as i32;
+    let n = exponent + 1;
+    if k <= n && n <= 21 {
+        output.write_str(digits)?;
+        for _ in 0..(n - k) {
+            output.write_char('0')?;
+        }
+    } else if 0 < n && n <= 21 {
+        let (int_digits, frac_digits) = digits.split_at(n as usize);
+        write!(output, "{}.{}", int_digits, frac_digits)?;
+    } else if -6 < n && n <= 0 {
+        output.write_str("0.")?;
+        for _ in 0..-n {
+            output.write_char('0')?;
+        }
+        output.write_str(digits)?;
+    } else {
+        let (first, rest) = digits.split_at(1);
+        output.write_str(first)?;
+        if !rest.is_empty() {
+            write!(output, ".{}", rest)?;
+        }
+        let sign = if n > 0 { '+' } else { '-' };
+        write!(output, "e{}{}", sign, (n - 1).abs())?;
+    }
+    Ok(())
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const HEX_DIGITS_UPPER: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Writes the lowest `digits` nibbles of `code` as hex, most significant first.
+fn write_hex<W: core::fmt::Write + ?Sized>(
+    code: u32,
+    digits: u32,
+    uppercase: bool,
+    output: &mut W,
+) -> core::fmt::Result {
+    let hex_digits = if uppercase {
+        HEX_DIGITS_UPPER
+    } else {
+        HEX_DIGITS
+    };
+    for i in (0..digits).rev() {
+        let nibble = (code >> (i * 4)) & 0xf;
+        output.write_char(hex_digits[nibble as usize] as char)?;
+    }
+    Ok(())
+}
+
+/// The reason a [`DeserializeError`] occurred.
+///
+/// More kinds may be added in minor releases, so a `match` on this needs a wildcard arm for
+/// the ones it does not handle.
+///
+/// # Example
+///
+/// ```rust
+/// use jsurl::ErrorKind;
+///
+/// let message = match jsurl::deserialize("~(a~1").unwrap_err().kind() {
+///     ErrorKind::UnexpectedEof => "truncated link",
+///     ErrorKind::InputTooLong | ErrorKind::DepthLimitExceeded => "link too large",
+///     _ => "malformed link",
+/// };
+/// assert_eq!(message, "truncated link");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The input ended before a complete value was read.
+    UnexpectedEof,
+    /// A character appeared where it is not allowed by the grammar.
+    UnexpectedChar(char),
+    /// A `*` or `**` escape was malformed or did not encode a valid character. The error's
+    /// position is the first char of the escape that is not a hex digit, or the `*` that
+    /// starts it if its digits are valid but do not encode a char.
+    InvalidEscape,
+    /// A bare token started with `-` or a digit, but was not a number in the grammar
+    /// described at [`deserialize`], or was a float out of range.
+    InvalidNumber,
+    /// A bare token was neither a number nor one of `null`, `true`, or `false`.
+    InvalidLiteral,
+    /// Arrays and objects were nested deeper than [`Deserializer::max_depth`] allows.
+    DepthLimitExceeded,
+    /// The input was not valid UTF-8.
+    InvalidUtf8,
+    /// A `%` was not followed by two hex digits while [`Deserializer::percent_decode`] was
+    /// enabled.
+    InvalidPercentEncoding,
+    /// An object contained the same key twice while
+    /// [`Deserializer::reject_duplicate_keys`] was enabled.
+    DuplicateKey,
+    /// A complete value was read, but more input followed it. The error's position is where
+    /// the extra input begins. This often means that a whole query string with several
+    /// parameters was passed in, rather than a single value.
+    TrailingCharacters,
+    /// The input was longer than [`Deserializer::max_input_len`] allows. The error's
+    /// position is the limit.
+    InputTooLong,
+    /// A number was longer than [`Deserializer::max_number_len`] allows. The error's
+    /// position is the start of the number.
+    NumberTooLong,
+}
+
+impl core::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ErrorKind::UnexpectedEof => f.write_str("unexpected end of input"),
+            ErrorKind::UnexpectedChar(c) => write!(f, "unexpected '{}'", c),
+            ErrorKind::InvalidEscape => f.write_str("invalid escape sequence"),
+            ErrorKind::InvalidNumber => f.write_str("invalid number"),
+            ErrorKind::InvalidLiteral => f.write_str("invalid literal"),
+            ErrorKind::DepthLimitExceeded => f.write_str("nesting depth limit exceeded"),
+            ErrorKind::InvalidUtf8 => f.write_str("invalid UTF-8"),
+            ErrorKind::InvalidPercentEncoding => f.write_str("invalid percent-encoding"),
+            ErrorKind::DuplicateKey => f.write_str("duplicate object key"),
+            ErrorKind::TrailingCharacters => f.write_str("trailing characters after value"),
+            ErrorKind::InputTooLong => f.write_str("input too long"),
+            ErrorKind::NumberTooLong => f.write_str("number too long"),
+        }
+    }
+}
+
+/// An error returned when the input is not valid jsurl.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeserializeError {
+    kind: ErrorKind,
+    position: usize,
+    key: Option<String>,
+}
+
+impl DeserializeError {
+    fn new(kind: ErrorKind, position: usize) -> Self {
+        DeserializeError {
+            kind,
+            position,
+            key: None,
+        }
+    }
+
+    /// The reason parsing failed.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The index (in chars, not bytes) into the input where parsing failed.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The name of the query parameter whose value failed to parse, for errors returned by
+    /// [`deserialize_query`].
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+}
+
+impl core::fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.key {
+            Some(key) => write!(
+                f,
+                "error at char {} in parameter '{}': {}",
+                self.position, key, self.kind
+            ),
+            None => write!(f, "error at char {}: {}", self.position, self.kind),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DeserializeError {}
+
+/// Deserializes a jsurl string.
+///
+/// The input must consist of exactly one value: surrounding whitespace, a stray leading or
+/// trailing `~`, or anything else after the value is an error. Input left over after a
+/// complete value fails with [`ErrorKind::TrailingCharacters`]. See [`deserialize_lenient`]
+/// for input that may still carry a `?name=` query prefix.
+///
+/// Numbers follow the JSON grammar: an optional `-`, an integer part without leading
+/// zeros, then an optional fraction and exponent. So `~1e5` and `~-0.5` are read, while
+/// `~+1`, `~.5`, `~0x10`, `~01` and `~1.2.3` are errors. The JavaScript reference parses
+/// numbers with `parseFloat` instead, which reads `~0x10` as `0` and `~1.2.3` as `1.2`.
+///
+/// In particular, a `0` can only start the integer part if it is the whole integer part,
+/// as in `~0`, `~-0` or `~0.25`. `~00` and `~007` are errors, where `parseFloat` would
+/// read `0` and `7`. jsurl serializers never write leading zeros, so this only rejects
+/// hand-written or corrupted input.
+///
+/// Each string and key in the result is copied out of the input with a single allocation,
+/// except for escaped strings longer than a few hundred chars. For the 10000 short escaped
+/// strings of `bench_deserialize_escaped_strings` in `examples/bench.rs`, that is 10013
+/// allocations in all rather than the 30003 of growing each string as it is decoded. Decoding
+/// into an existing `serde_json::Value` is not supported, since `serde_json::Map` cannot
+/// keep its allocations once cleared, and the strings it holds have to be owned anyway.
+///
+/// The input can be given as anything that derefs to a `str`, such as a `&str`, a `String`
+/// or the `Cow<str>` that percent-decoding crates return.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+/// use std::borrow::Cow;
+///
+/// assert_eq!(jsurl::deserialize("~(a~1)").unwrap(), json!({"a": 1}));
+/// assert_eq!(jsurl::deserialize(String::from("~'x")).unwrap(), json!("x"));
+/// let decoded: Cow<str> = Cow::Owned(String::from("~(~true)"));
+/// assert_eq!(jsurl::deserialize(decoded).unwrap(), json!([true]));
+/// ```
+pub fn deserialize<S: AsRef<str>>(s: S) -> Result<serde_json::Value, DeserializeError> {
+    Deserializer::new().deserialize(s)
+}
+
+/// Deserializes a jsurl string with a custom [`Builder`], to build values of a type other
+/// than `serde_json::Value` without going through one.
+///
+/// The input is checked exactly as by [`deserialize`], which is the same as calling this
+/// with a [`ValueBuilder`].
+///
+/// # Example
+///
+/// ```rust
+/// use std::borrow::Cow;
+///
+/// /// Counts the scalars in a value instead of building it.
+/// struct Count;
+///
+/// impl jsurl::Builder for Count {
+///     type Value = usize;
+///     type Array = usize;
+///     type Object = usize;
+///
+///     fn build_null(&mut self) -> usize { 1 }
+///     fn build_bool(&mut self, _: bool) -> usize { 1 }
+///     fn build_number(&mut self, _: &str) -> Option<usize> { Some(1) }
+///     fn build_string(&mut self, _: Cow<'_, str>) -> usize { 1 }
+///     fn begin_array(&mut self) -> usize { 0 }
+///     fn push(&mut self, array: &mut usize, value: usize) { *array += value }
+///     fn end_array(&mut self, array: usize) -> usize { array }
+///     fn begin_object(&mut self) -> usize { 0 }
+///     fn insert(&mut self, object: &mut usize, _: Cow<'_, str>, value: usize) { *object += value }
+///     fn end_object(&mut self, object: usize) -> usize { object }
+/// }
+///
+/// assert_eq!(jsurl::deserialize_with("~(a~1~b~(~true~'x~()))", &mut Count).unwrap(), 3);
+/// ```
+pub fn deserialize_with<B: Builder>(
+    s: &str,
+    builder: &mut B,
+) -> Result<B::Value, DeserializeError> {
+    Deserializer::new().deserialize_with(s, builder)
+}
+
+/// Builds values as they are parsed, for [`deserialize_with`].
+///
+/// The parser calls these methods bottom-up: the values inside an array or object are
+/// built, and handed to [`Builder::push`] or [`Builder::insert`], before the container
+/// itself is finished with [`Builder::end_array`] or [`Builder::end_object`]. Containers
+/// that are still being filled are held by the parser, so the builder itself only needs
+/// state of its own, such as an interner for keys.
+pub trait Builder {
+    /// A finished value.
+    type Value;
+    /// An array that is still being filled.
+    type Array;
+    /// An object that is still being filled.
+    type Object;
+
+    fn build_null(&mut self) -> Self::Value;
+    fn build_bool(&mut self, b: bool) -> Self::Value;
+    /// Builds a number from its text, which matches the JSON number grammar (see
+    /// [`deserialize`]). Returning `None` rejects it with [`ErrorKind::InvalidNumber`], for
+    /// example because it is out of range.
+    fn build_number(&mut self, token: &str) -> Option<Self::Value>;
+    /// Builds a string. It is borrowed from the input unless it contained escapes.
+    fn build_string(&mut self, s: Cow<'_, str>) -> Self::Value;
+    fn begin_array(&mut self) -> Self::Array;
+    fn push(&mut self, array: &mut Self::Array, value: Self::Value);
+    fn end_array(&mut self, array: Self::Array) -> Self::Value;
+    fn begin_object(&mut self) -> Self::Object;
+    /// Adds an entry to an object. Keys come in input order, and with
+    /// [`Deserializer::reject_duplicate_keys`], each key of an object only once.
+    fn insert(&mut self, object: &mut Self::Object, key: Cow<'_, str>, value: Self::Value);
+    fn end_object(&mut self, object: Self::Object) -> Self::Value;
+}
+
+/// The [`Builder`] behind [`deserialize`], which builds a `serde_json::Value`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValueBuilder;
+
+impl Builder for ValueBuilder {
+    type Value = serde_json::Value;
+    type Array = Vec<serde_json::Value>;
+    type Object = serde_json::Map<String, serde_json::Value>;
+
+    fn build_null(&mut self) -> Self::Value {
+        serde_json::Value::Null
+    }
+
+    fn build_bool(&mut self, b: bool) -> Self::Value {
+        serde_json::Value::Bool(b)
+    }
+
+    fn build_number(&mut self, token: &str) -> Option<Self::Value> {
+        number_from_token(token).map(serde_json::Value::Number)
+    }
+
+    fn build_string(&mut self, s: Cow<'_, str>) -> Self::Value {
+        serde_json::Value::String(s.into_owned())
+    }
+
+    fn begin_array(&mut self) -> Self::Array {
+        Vec::new()
+    }
+
+    fn push(&mut self, array: &mut Self::Array, value: Self::Value) {
+        array.push(value);
+    }
+
+    fn end_array(&mut self, array: Self::Array) -> Self::Value {
+        serde_json::Value::Array(array)
+    }
+
+    fn begin_object(&mut self) -> Self::Object {
+        serde_json::Map::new()
+    }
+
+    fn insert(&mut self, object: &mut Self::Object, key: Cow<'_, str>, value: Self::Value) {
+        object.insert(key.into_owned(), value);
+    }
+
+    fn end_object(&mut self, object: Self::Object) -> Self::Value {
+        serde_json::Value::Object(object)
+    }
+}
+
+/// Converts a token that matches the JSON number grammar to a `serde_json::Number`, or
+/// `None` if it is out of range.
+fn number_from_token(token: &str) -> Option<serde_json::Number> {
+    // with `arbitrary_precision`, serde_json keeps the exact digits of the token, so
+    // integers beyond the `u64` range survive a round trip
+    if cfg!(feature = "arbitrary_precision") {
+        return token.parse().ok();
+    }
+    // otherwise floats are parsed by `f64::from_str`, which always finds the closest `f64`:
+    // serde_json's parser can be off by one in the last digit unless its `float_roundtrip`
+    // feature is enabled. As with serde_json, `-0` is a float
+    if token != "-0" {
+        if let Ok(n) = token.parse::<u64>() {
+            return Some(n.into());
+        }
+        if let Ok(n) = token.parse::<i64>() {
+            return Some(n.into());
+        }
+    }
+    serde_json::Number::from_f64(token.parse().ok()?)
+}
+
+/// Deserializes a jsurl string into an [`OrderedValue`], whose objects keep their keys in
+/// input order. Requires the `ordered` feature.
+///
+/// `serde_json::Value` only keeps keys in input order if serde_json's `preserve_order`
+/// feature is enabled, which any crate in the dependency graph can turn on or leave off.
+/// An `OrderedValue` keeps them in order regardless. Other than that, this is the same as
+/// [`deserialize`]: a key that appears twice keeps its first position and its last value.
+///
+/// # Example
+///
+/// ```rust
+/// use jsurl::OrderedValue;
+///
+/// let value = jsurl::deserialize_ordered("~(z~1~a~(y~2~b~3))").unwrap();
+/// let OrderedValue::Object(object) = &value else { panic!() };
+/// assert_eq!(object.keys().collect::<Vec<_>>(), ["z", "a"]);
+/// assert_eq!(value.to_string(), "~(z~1~a~(y~2~b~3))");
+/// ```
+#[cfg(feature = "ordered")]
+pub fn deserialize_ordered(s: &str) -> Result<OrderedValue, DeserializeError> {
+    Deserializer::new().deserialize_ordered(s)
+}
+
+/// A JSON value whose objects keep their keys in insertion order, returned by
+/// [`deserialize_ordered`]. Requires the `ordered` feature.
+///
+/// It displays as jsurl, with the keys of each object in their order in the map.
+#[cfg(feature = "ordered")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderedValue {
+    Null,
+    Bool(bool),
+    Number(serde_json::Number),
+    String(String),
+    Array(Vec<OrderedValue>),
+    Object(indexmap::IndexMap<String, OrderedValue>),
+}
+
+#[cfg(feature = "ordered")]
+impl From<OrderedValue> for serde_json::Value {
+    /// Converts to a `serde_json::Value`. Its objects keep the order of the keys only if
+    /// serde_json's `preserve_order` feature is enabled.
+    fn from(value: OrderedValue) -> Self {
+        match value {
+            OrderedValue::Null => serde_json::Value::Null,
+            OrderedValue::Bool(b) => serde_json::Value::Bool(b),
+            OrderedValue::Number(n) => serde_json::Value::Number(n),
+            OrderedValue::String(s) => serde_json::Value::String(s),
+            OrderedValue::Array(a) => a.into_iter().map(serde_json::Value::from).collect(),
+            OrderedValue::Object(o) => serde_json::Value::Object(
+                o.into_iter()
+                    .map(|(k, v)| (k, serde_json::Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl core::fmt::Display for OrderedValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let serializer = Serializer::new();
+        match self {
+            OrderedValue::Null => f.write_str("~null"),
+            OrderedValue::Bool(b) => write!(f, "~{}", b),
+            OrderedValue::Number(n) => {
+                serializer.serialize_to_writer(&serde_json::Value::Number(n.clone()), f)
+            }
+            OrderedValue::String(s) => {
+                f.write_str("~'")?;
+                serializer.encode_string(s, f)
+            }
+            OrderedValue::Array(a) => {
+                f.write_str("~(")?;
+                if a.is_empty() {
+                    f.write_char('~')?;
+                }
+                for v in a {
+                    v.fmt(f)?;
+                }
+                f.write_char(')')
+            }
+            OrderedValue::Object(o) => {
+                f.write_str("~(")?;
+                for (i, (k, v)) in o.iter().enumerate() {
+                    // as in `Serializer::write_entries`
+                    if i > 0 || k.is_empty() {
+                        f.write_char('~')?;
+                    }
+                    serializer.encode_string(k, f)?;
+                    v.fmt(f)?;
+                }
+                f.write_char(')')
+            }
+        }
+    }
+}
+
+/// The [`Builder`] behind [`deserialize_ordered`].
+#[cfg(feature = "ordered")]
+struct OrderedBuilder;
+
+#[cfg(feature = "ordered")]
+impl Builder for OrderedBuilder {
+    type Value = OrderedValue;
+    type Array = Vec<OrderedValue>;
+    type Object = indexmap::IndexMap<String, OrderedValue>;
+
+    fn build_null(&mut self) -> Self::Value {
+        OrderedValue::Null
+    }
+
+    fn build_bool(&mut self, b: bool) -> Self::Value {
+        OrderedValue::Bool(b)
+    }
+
+    fn build_number(&mut self, token: &str) -> Option<Self::Value> {
+        number_from_token(token).map(OrderedValue::Number)
+    }
+
+    fn build_string(&mut self, s: Cow<'_, str>) -> Self::Value {
+        OrderedValue::String(s.into_owned())
+    }
+
+    fn begin_array(&mut self) -> Self::Array {
+        Vec::new()
+    }
+
+    fn push(&mut self, array: &mut Self::Array, value: Self::Value) {
+        array.push(value);
+    }
+
+    fn end_array(&mut self, array: Self::Array) -> Self::Value {
+        OrderedValue::Array(array)
+    }
+
+    fn begin_object(&mut self) -> Self::Object {
+        indexmap::IndexMap::new()
+    }
+
+    fn insert(&mut self, object: &mut Self::Object, key: Cow<'_, str>, value: Self::Value) {
+        object.insert(key.into_owned(), value);
+    }
+
+    fn end_object(&mut self, object: Self::Object) -> Self::Value {
+        OrderedValue::Object(object)
+    }
+}
+
+/// Deserializes a jsurl string that may be prefixed by a single query parameter name, as
+/// in `?q=~(a~1)` or `q=~(a~1)`.
+///
+/// Exactly one leading `name=` segment (optionally preceded by `?`) is skipped, as long as
+/// the name contains no `~`, `&`, or `=`. The remainder must be valid jsurl as accepted by
+/// [`deserialize`]; error positions still refer to the original input.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// assert_eq!(jsurl::deserialize_lenient("?q=~(a~1)").unwrap(), json!({"a": 1}));
+/// assert_eq!(jsurl::deserialize_lenient("q=~(a~1)").unwrap(), json!({"a": 1}));
+/// assert_eq!(jsurl::deserialize_lenient("~(a~1)").unwrap(), json!({"a": 1}));
+/// ```
+pub fn deserialize_lenient(s: &str) -> Result<serde_json::Value, DeserializeError> {
+    Deserializer::new().deserialize_lenient(s)
+}
+
+/// Serializes a value for use as a query parameter value in a URL built with a library
+/// that follows the WHATWG URL standard, such as the `url` crate.
+///
+/// Such libraries leave every char of jsurl as it is in a query, except for `'`, which they
+/// percent-encode in `http` and `https` URLs, turning `~'` into `~%27`. This writes it as
+/// `%27` up front, so that setting the query with the result does not change it. The value
+/// must not be form-encoded again, as `url::form_urlencoded` and `append_pair` would do.
+///
+/// Read the value back with [`from_url_query_value`].
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let value = json!({"name": "O'Brien"});
+/// let query = format!("q={}", jsurl::to_url_query_value(&value));
+/// assert_eq!(query, "q=~(name~%27O*27Brien)");
+/// assert_eq!(jsurl::from_url_query_value(&query[2..]).unwrap(), value);
+/// ```
+pub fn to_url_query_value(obj: &serde_json::Value) -> String {
+    serialize(obj).replace('\'', "%27")
+}
+
+/// Deserializes a query parameter value written by [`to_url_query_value`].
+///
+/// The value may be given as it appears in the URL, or after the URL library has
+/// percent-decoded it, as `url::Url::query_pairs` does. This is [`deserialize`] with
+/// [`Deserializer::percent_decode`] enabled, so values whose `~`, `(` or `)` were
+/// percent-encoded along the way are read as well.
+pub fn from_url_query_value(s: &str) -> Result<serde_json::Value, DeserializeError> {
+    Deserializer::new().percent_decode(true).deserialize(s)
+}
+
+/// Serializes a value and percent-encodes the result, so that it can be put after `?key=`
+/// or `&key=` as it is, whatever handles the URL afterwards.
+///
+/// Every char other than the ASCII letters and digits, `-`, `_` and `.` is percent-encoded,
+/// as `%XX` with uppercase hex digits. Of the chars jsurl is written with, that is `~`, `'`,
+/// `(`, `)`, `*` and `!`, which URL libraries, form encoders and link detectors treat in
+/// different ways. The result is longer than [`to_url_query_value`], which only encodes
+/// `'`, but no encoder or decoder along the way changes its meaning.
+///
+/// Read the value back with [`deserialize_url_safe`].
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let value = json!({"a": [1, "x y"]});
+/// let query = format!("q={}", jsurl::serialize_url_safe(&value));
+/// assert_eq!(query, "q=%7E%28a%7E%28%7E1%7E%27x%2A20y%29%29");
+/// assert_eq!(jsurl::deserialize_url_safe(&query[2..]).unwrap(), value);
+/// ```
+pub fn serialize_url_safe(obj: &serde_json::Value) -> String {
+    let serialized = serialize(obj);
+    let mut output = String::with_capacity(serialized.len() * 3);
+    for &b in serialized.as_bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.') {
+            output.push(b as char);
+        } else {
+            output.push('%');
+            output.push(HEX_DIGITS_UPPER[(b >> 4) as usize] as char);
+            output.push(HEX_DIGITS_UPPER[(b & 0xf) as usize] as char);
+        }
+    }
+    output
+}
+
+/// Deserializes a value written by [`serialize_url_safe`], percent-decoding it first.
+///
+/// This is the same as [`from_url_query_value`], so the value may also be given after a
+/// URL library has decoded some or all of its `%XX` escapes.
+pub fn deserialize_url_safe(s: &str) -> Result<serde_json::Value, DeserializeError> {
+    from_url_query_value(s)
+}
+
+/// Deep-merges the jsurl value `patch` into `base`, and serializes the result.
+///
+/// This is the same as [`merge_with`] with [`ArrayMerge::Replace`]:
+///
+/// - Where both sides have an object, the objects are merged key by key, recursively. Keys
+///   only in `base` are kept, and new keys from `patch` are added, after the others if
+///   objects keep their order (see the `std` feature).
+/// - Anywhere else, the value from `patch` replaces the one from `base`: arrays, scalars,
+///   and an object on one side facing anything else on the other. A `null` in `patch` is
+///   kept as `null`, rather than removing the key as a JSON Merge Patch would.
+///
+/// If `base` is not valid jsurl its error is returned, otherwise that of `patch`, if any.
+///
+/// # Example
+///
+/// ```rust
+/// let base = "~(cols~(~'x~'y)~filter~(min~2~tag~'a)~page~1)";
+/// let merged = jsurl::merge(base, "~(cols~(~'z)~filter~(min~5))").unwrap();
+/// assert_eq!(merged, "~(cols~(~'z)~filter~(min~5~tag~'a)~page~1)");
+/// ```
+pub fn merge(base: &str, patch: &str) -> Result<String, DeserializeError> {
+    merge_with(base, patch, ArrayMerge::Replace)
+}
+
+/// Like [`merge`], but with a choice of how arrays that appear on both sides are merged.
+///
+/// ```rust
+/// use jsurl::ArrayMerge;
+///
+/// let merged = jsurl::merge_with("~(cols~(~'x~'y))", "~(cols~(~'z))", ArrayMerge::Concat);
+/// assert_eq!(merged.unwrap(), "~(cols~(~'x~'y~'z))");
+/// ```
+pub fn merge_with(base: &str, patch: &str, arrays: ArrayMerge) -> Result<String, DeserializeError> {
+    let mut value = deserialize(base)?;
+    merge_values(&mut value, deserialize(patch)?, arrays);
+    Ok(serialize(&value))
+}
+
+/// How [`merge_with`] merges an array in the patch with an array in the base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ArrayMerge {
+    /// The patch's array replaces the base's, as any other value would.
+    #[default]
+    Replace,
+    /// The patch's elements are appended to the base's.
+    Concat,
+}
+
+fn merge_values(base: &mut serde_json::Value, patch: serde_json::Value, arrays: ArrayMerge) {
+    match (base, patch) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value, arrays),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (serde_json::Value::Array(base), serde_json::Value::Array(patch))
+            if arrays == ArrayMerge::Concat =>
+        {
+            base.extend(patch);
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
+/// Deserializes the output of [`serialize_compact`], a jsurl value without its leading `~`.
+///
+/// Error positions refer to the compact input as given.
+pub fn deserialize_compact(s: &str) -> Result<serde_json::Value, DeserializeError> {
+    Deserializer::new().deserialize_compact(s)
+}
+
+/// Deserializes every parameter of a query string such as `a=~(x~1)&b=~'hello`, returning
+/// the names and values in order.
+///
+/// A leading `?` is skipped, as are empty parameters (for example from `&&` or a trailing
+/// `&`). Each value must be valid jsurl as accepted by [`deserialize`]; a parameter without
+/// a `=`, or with nothing after it, fails with [`ErrorKind::UnexpectedEof`]. Names are
+/// returned as they appear, without any percent-decoding.
+///
+/// When a value fails to parse, the error's [`DeserializeError::key`] is the name of the
+/// parameter, and its position refers to the whole query string.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let params = jsurl::deserialize_query("?a=~(x~1)&b=~'hello").unwrap();
+/// assert_eq!(
+///     params,
+///     [
+///         ("a".to_string(), json!({"x": 1})),
+///         ("b".to_string(), json!("hello")),
+///     ]
+/// );
+///
+/// let err = jsurl::deserialize_query("a=~1&b=~'x*zz").unwrap_err();
+/// assert_eq!(err.key(), Some("b"));
+/// assert_eq!(err.position(), 11);
+/// ```
+pub fn deserialize_query(
+    query: &str,
+) -> Result<Vec<(String, serde_json::Value)>, DeserializeError> {
+    Deserializer::new().deserialize_query(query)
+}
+
+fn str_from_utf8(input: &[u8]) -> Result<&str, DeserializeError> {
+    core::str::from_utf8(input).map_err(|e| {
+        let valid = &input[..e.valid_up_to()];
+        // the prefix is valid UTF-8, so counting non-continuation bytes counts chars
+        let position = valid.iter().filter(|&&b| (b & 0xc0) != 0x80).count();
+        DeserializeError::new(ErrorKind::InvalidUtf8, position)
+    })
+}
+
+/// Decodes `%XX` escapes into the bytes they represent.
+fn percent_decode(s: &str) -> Result<Vec<u8>, DeserializeError> {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = |b: Option<&u8>| b.and_then(|&b| hex_digit_to_value(b as char));
+            match (hex(bytes.get(i + 1)), hex(bytes.get(i + 2))) {
+                (Some(high), Some(low)) => result.push(((high << 4) | low) as u8),
+                _ => {
+                    return Err(DeserializeError::new(
+                        ErrorKind::InvalidPercentEncoding,
+                        s[..i].chars().count(),
+                    ))
+                }
+            }
+            i += 3;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(result)
+}
+
+/// Splits off a leading `?name=` or `name=` segment, if there is one, before the first
+/// `separator`.
+fn split_query_prefix(s: &str, separator: char) -> (&str, &str) {
+    let rest = s.strip_prefix('?').unwrap_or(s);
+    match rest.find([separator, '&', '=']) {
+        Some(i) if rest.as_bytes()[i] == b'=' => s.split_at(s.len() - rest.len() + i + 1),
+        _ => ("", s),
+    }
+}
+
+/// Deserializes a jsurl string without recursing, for inputs nested too deeply for
+/// [`deserialize`].
+///
+/// The result is identical to [`deserialize`], except that no nesting depth limit is
+/// applied. Keep in mind that dropping, cloning, or comparing a `serde_json::Value` is
+/// itself recursive, so extremely deep values still need to be handled with care.
+pub fn deserialize_iterative(s: &str) -> Result<serde_json::Value, DeserializeError> {
+    Deserializer::new()
+        .max_depth(usize::MAX)
+        .deserialize_iterative(s)
+}
+
+/// Deserializes a jsurl object into its entries, in the order they appear in the input.
+///
+/// The order of keys in the objects returned by [`deserialize`] depends on serde_json: with
+/// the `std` feature (enabled by default), serde_json's `preserve_order` feature is enabled
+/// and objects keep their input order, but without it keys are sorted. This function
+/// guarantees input order for the top-level object regardless of features. Nested objects
+/// are still returned as `serde_json::Value`s.
+///
+/// The input must be an object; anything else fails with [`ErrorKind::UnexpectedChar`].
+/// Unless [`Deserializer::reject_duplicate_keys`] is enabled, a key that appears more than
+/// once is returned once for every time it appears.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let entries = jsurl::deserialize_entries("~(b~1~a~(~2))").unwrap();
+/// assert_eq!(
+///     entries,
+///     [("b".to_string(), json!(1)), ("a".to_string(), json!([2]))]
+/// );
+/// ```
+pub fn deserialize_entries(s: &str) -> Result<Vec<(String, serde_json::Value)>, DeserializeError> {
+    Deserializer::new().deserialize_entries(s)
+}
+
+/// Checks that `s` is valid jsurl, without building a `serde_json::Value`.
+///
+/// This accepts and rejects exactly the same inputs as [`deserialize`], with the same
+/// errors, but strings without escapes are never copied and no arrays or objects are
+/// allocated. Use it to cheaply gate untrusted input.
+///
+/// # Example
+///
+/// ```rust
+/// assert!(jsurl::validate("~(a~(~1~'two))").is_ok());
+/// assert_eq!(
+///     jsurl::validate("~(a~1").unwrap_err().kind(),
+///     jsurl::ErrorKind::UnexpectedEof
+/// );
+/// ```
+pub fn validate(s: &str) -> Result<(), DeserializeError> {
+    Deserializer::new().validate(s)
+}
+
+/// Deserializes jsurl from raw bytes, such as a query string taken straight from an HTTP
+/// request.
+///
+/// For valid UTF-8 input this produces the same result as [`deserialize`]. Invalid UTF-8
+/// fails with [`ErrorKind::InvalidUtf8`], positioned at the first offending byte.
+pub fn deserialize_bytes(input: &[u8]) -> Result<serde_json::Value, DeserializeError> {
+    Deserializer::new().deserialize_bytes(input)
+}
+
+/// Parses jsurl into a stream of [`Event`]s instead of building a `serde_json::Value`.
+///
+/// This allows filtering or transforming large inputs while only keeping the current
+/// token in memory. The iterator stops after the first error.
+///
+/// # Example
+///
+/// ```rust
+/// use jsurl::Event;
+///
+/// let events: Vec<Event> = jsurl::events("~(a~(~true~null))")
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(
+///     events,
+///     [
+///         Event::BeginObject,
+///         Event::Key("a".to_string()),
+///         Event::BeginArray,
+///         Event::Bool(true),
+///         Event::Null,
+///         Event::End,
+///         Event::End,
+///     ]
+/// );
+/// ```
+pub fn events(s: &str) -> Events<'_> {
+    DEFAULT_DESERIALIZER.events(s)
+}
+
+/// Creates a [`ParseCursor`] over `s`, for reading values one at a time and handling the
+/// input between them yourself.
+///
+/// # Example
+///
+/// ```rust
+/// use serde_json::json;
+///
+/// let mut cursor = jsurl::cursor("~(a~1)&~(~2)");
+/// assert_eq!(cursor.parse_value().unwrap(), json!({"a": 1}));
+/// assert_eq!(cursor.remaining(), "&~(~2)");
+/// assert_eq!(cursor.next_char(), Some('&'));
+/// assert_eq!(cursor.parse_value().unwrap(), json!([2]));
+/// assert_eq!(cursor.peek_char(), None);
+/// ```
+pub fn cursor(s: &str) -> ParseCursor<'_> {
+    DEFAULT_DESERIALIZER.cursor(s)
+}
+
+/// The default value of [`Deserializer::max_depth`].
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+static DEFAULT_DESERIALIZER: Deserializer = Deserializer::new();
+
+/// A configurable deserializer.
+///
+/// The free function [`deserialize`] is equivalent to `Deserializer::new().deserialize(..)`.
+#[derive(Debug, Clone)]
+pub struct Deserializer {
+    max_depth: usize,
+    reject_duplicate_keys: bool,
+    percent_decode: bool,
+    allow_trailing_comma_style: bool,
+    max_input_len: usize,
+    lossy: bool,
+    plus_as_space: bool,
+    trim: bool,
+    max_number_len: usize,
+    numbers_as_strings: bool,
+    separator: char,
+}
+
+impl Default for Deserializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deserializer {
+    /// Creates a deserializer with every option at its default, which matches
+    /// [`deserialize`].
+    ///
+    /// Since this is a `const fn`, the result can be stored in a `static`.
+    pub const fn new() -> Self {
+        Deserializer {
+            max_depth: DEFAULT_MAX_DEPTH,
+            reject_duplicate_keys: false,
+            percent_decode: false,
+            allow_trailing_comma_style: false,
+            max_input_len: usize::MAX,
+            lossy: false,
+            plus_as_space: false,
+            trim: false,
+            max_number_len: usize::MAX,
+            numbers_as_strings: false,
+            separator: VALUE_MARKER,
+        }
+    }
+
+    /// The maximum number of arrays and objects that may be nested inside each other.
+    ///
+    /// Inputs that nest deeper fail with [`ErrorKind::DepthLimitExceeded`] instead of
+    /// recursing further, which protects against stack overflows on untrusted input.
+    /// Defaults to [`DEFAULT_MAX_DEPTH`].
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Fail with [`ErrorKind::DuplicateKey`] when an object contains the same key more than
+    /// once, instead of letting the last value win. Disabled by default.
+    pub fn reject_duplicate_keys(mut self, reject_duplicate_keys: bool) -> Self {
+        self.reject_duplicate_keys = reject_duplicate_keys;
+        self
+    }
+
+    /// Percent-decode the input before parsing it, for input that went through a layer that
+    /// escaped jsurl's structural characters (for example `~` as `%7E` and `*` as `%2A`).
+    ///
+    /// Every `%` must start a valid `%XX` escape, otherwise parsing fails with
+    /// [`ErrorKind::InvalidPercentEncoding`] at the position of the `%`. jsurl itself never
+    /// emits a raw `%` (it is written as `*25`), so this only rejects input that was not
+    /// percent-encoded to begin with. The decoded bytes must be valid UTF-8. Positions of
+    /// errors found after decoding refer to the decoded input. Disabled by default.
+    pub fn percent_decode(mut self, percent_decode: bool) -> Self {
+        self.percent_decode = percent_decode;
+        self
+    }
+
+    /// The maximum length of the input, in chars.
+    ///
+    /// Longer inputs fail with [`ErrorKind::InputTooLong`] before any parsing is done, which
+    /// guards against a single enormous string or flat array exhausting memory. Unlike
+    /// [`Deserializer::max_depth`], this bounds the total size of the result. The limit
+    /// applies to the input as given, before percent-decoding, and to the whole query string
+    /// for [`Deserializer::deserialize_query`]. Unlimited by default.
+    pub fn max_input_len(mut self, max_input_len: usize) -> Self {
+        self.max_input_len = max_input_len;
+        self
+    }
+
+    /// The maximum length of a single number, in chars, counting its sign, decimal point
+    /// and exponent.
+    ///
+    /// Longer numbers fail with [`ErrorKind::NumberTooLong`] before they are parsed. Parsing
+    /// a number takes time in proportion to its length, and with the `arbitrary_precision`
+    /// feature every digit is kept, so this bounds the cost of a single huge number that
+    /// [`Deserializer::max_input_len`] alone would let through. Unlimited by default; no
+    /// `f64` needs more than about 25 chars.
+    pub fn max_number_len(mut self, max_number_len: usize) -> Self {
+        self.max_number_len = max_number_len;
+        self
+    }
+
+    /// Keep numbers as strings of their text as written, instead of parsing them, for
+    /// callers that pass values through and must not change a digit. Disabled by default.
+    ///
+    /// Numbers are still checked against the grammar, so invalid input is rejected as
+    /// usual, but floats out of range are accepted. The result does not compare equal to
+    /// the one parsed without this option, since `2` becomes `"2"`, and it serializes with
+    /// its numbers as strings, as in `~'2`. With [`Deserializer::deserialize_with`],
+    /// numbers are handed to [`Builder::build_string`].
+    ///
+    /// ```rust
+    /// use jsurl::Deserializer;
+    /// use serde_json::json;
+    ///
+    /// let raw = Deserializer::new().numbers_as_strings(true);
+    /// let value = raw.deserialize("~(a~1.50~b~-0~c~1e400~d~'1)").unwrap();
+    /// assert_eq!(value, json!({"a": "1.50", "b": "-0", "c": "1e400", "d": "1"}));
+    /// ```
+    pub fn numbers_as_strings(mut self, numbers_as_strings: bool) -> Self {
+        self.numbers_as_strings = numbers_as_strings;
+        self
+    }
+
+    /// Accept a redundant `~` directly before the `)` that closes a non-empty array or an
+    /// object, as commonly left behind when editing jsurl by hand: `~(a~1~)` is read as
+    /// `~(a~1)`, and `~(~1~2~)` as `~(~1~2)`.
+    ///
+    /// Only a single `~` right before the `)` is tolerated. Doubled separators between
+    /// entries, such as `~(a~1~~b~2)`, are still rejected, and `~(~)` remains the empty
+    /// array. Disabled by default.
+    pub fn allow_trailing_comma_style(mut self, allow_trailing_comma_style: bool) -> Self {
+        self.allow_trailing_comma_style = allow_trailing_comma_style;
+        self
+    }
+
+    /// Decode a `**` escape of a lone UTF-16 surrogate as U+FFFD (the replacement character)
+    /// instead of failing with [`ErrorKind::InvalidEscape`].
+    ///
+    /// A surrogate is lone if it is a low surrogate, or a high surrogate that is not directly
+    /// followed by a `**` escape of a low surrogate. Whatever follows a lone high surrogate
+    /// is decoded on its own. No other malformed escape is replaced. This helps with URLs
+    /// from third parties that split strings in the middle of a surrogate pair. Disabled by
+    /// default.
+    ///
+    /// ```rust
+    /// use jsurl::Deserializer;
+    /// use serde_json::json;
+    ///
+    /// assert!(jsurl::deserialize("~'a**d83db").is_err());
+    /// let lossy = Deserializer::new().lossy(true);
+    /// assert_eq!(lossy.deserialize("~'a**d83db").unwrap(), json!("a\u{fffd}b"));
+    /// ```
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// Read a `+` in strings and keys as a space, to read the output of
+    /// [`Serializer::space_as_plus`]. A literal `+` must then be escaped as `*2b`.
+    ///
+    /// This is applied after [`Deserializer::percent_decode`], so `%2B` is read as a space
+    /// too. Disabled by default, since jsurl from other sources may contain literal `+`s.
+    pub fn plus_as_space(mut self, plus_as_space: bool) -> Self {
+        self.plus_as_space = plus_as_space;
+        self
+    }
+
+    /// Ignore a leading UTF-8 byte order mark (U+FEFF) and any ASCII whitespace around the
+    /// input, as often carried along by copy-pasted URLs. Disabled by default, so that
+    /// anything around the value is an error.
+    ///
+    /// Whitespace inside the input is not touched, since it can be part of a string. Error
+    /// positions still refer to the input as given.
+    ///
+    /// ```rust
+    /// use jsurl::Deserializer;
+    /// use serde_json::json;
+    ///
+    /// let input = "\u{feff}~(a~1)\n";
+    /// assert!(jsurl::deserialize(input).is_err());
+    /// let trimming = Deserializer::new().trim(true);
+    /// assert_eq!(trimming.deserialize(input).unwrap(), json!({"a": 1}));
+    /// ```
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Read the dialect written by [`Serializer::separator`], with `separator` in place of
+    /// every `~`. `~` is the default.
+    ///
+    /// Standard jsurl is then rejected, and so is any literal `~` in the input, which that
+    /// dialect never contains. The separator is swapped for `~` after
+    /// [`Deserializer::percent_decode`] and before parsing; error positions are unaffected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `separator` is not allowed by [`Serializer::separator`].
+    ///
+    /// ```rust
+    /// use jsurl::{Deserializer, ErrorKind};
+    /// use serde_json::json;
+    ///
+    /// let deserializer = Deserializer::new().separator('_');
+    /// assert_eq!(deserializer.deserialize("_(a_'b*5fc)").unwrap(), json!({"a": "b_c"}));
+    /// let err = deserializer.deserialize("_(a__1)").unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::UnexpectedChar('_'));
+    /// let err = deserializer.deserialize("~(a~1)").unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::UnexpectedChar('~'));
+    /// ```
+    pub fn separator(mut self, separator: char) -> Self {
+        assert_separator(separator);
+        self.separator = separator;
+        self
+    }
+
+    /// Deserializes jsurl from raw bytes. See [`deserialize_bytes`].
+    pub fn deserialize_bytes(&self, input: &[u8]) -> Result<serde_json::Value, DeserializeError> {
+        self.deserialize(str_from_utf8(input)?)
+    }
+
+    pub fn deserialize<S: AsRef<str>>(&self, s: S) -> Result<serde_json::Value, DeserializeError> {
+        self.run(s.as_ref(), parse_one)
+    }
+
+    /// Deserializes into an [`OrderedValue`]. See [`deserialize_ordered`].
+    #[cfg(feature = "ordered")]
+    pub fn deserialize_ordered(&self, s: &str) -> Result<OrderedValue, DeserializeError> {
+        self.deserialize_with(s, &mut OrderedBuilder)
+    }
+
+    /// Deserializes with a custom [`Builder`]. See [`deserialize_with`].
+    pub fn deserialize_with<B: Builder>(
+        &self,
+        s: &str,
+        builder: &mut B,
+    ) -> Result<B::Value, DeserializeError> {
+        self.run(s, |chars: &mut Reader| build_one(chars, builder))
+    }
+
+    /// Deserializes an object into its entries, in input order. See [`deserialize_entries`].
+    pub fn deserialize_entries(
+        &self,
+        s: &str,
+    ) -> Result<Vec<(String, serde_json::Value)>, DeserializeError> {
+        self.run(s, parse_object_entries)
+    }
+
+    /// Checks that `s` is valid jsurl without building its value. See [`validate`].
+    pub fn validate(&self, s: &str) -> Result<(), DeserializeError> {
+        self.run(s, skip_one)
+    }
+
+    /// Like [`Deserializer::deserialize`], but uses a parser that does not recurse. See
+    /// [`deserialize_iterative`].
+    ///
+    /// [`Deserializer::max_depth`] is still enforced, but since the parser's stack lives on
+    /// the heap it can safely be raised as far as needed.
+    pub fn deserialize_iterative(&self, s: &str) -> Result<serde_json::Value, DeserializeError> {
+        self.run(s, parse_one_iterative)
+    }
+
+    /// Deserializes every parameter of a query string. See [`deserialize_query`].
+    pub fn deserialize_query(
+        &self,
+        query: &str,
+    ) -> Result<Vec<(String, serde_json::Value)>, DeserializeError> {
+        self.check_input_len(query)?;
+        let params = query.strip_prefix('?').unwrap_or(query);
+        let mut offset = query.len() - params.len();
+        let mut result = Vec::new();
+        for param in params.split('&') {
+            let start = offset;
+            offset += param.len() + 1;
+            if param.is_empty() {
+                continue;
+            }
+            let (key, value, value_start) = match param.find('=') {
+                Some(i) => (&param[..i], &param[i + 1..], start + i + 1),
+                None => (param, "", start + param.len()),
+            };
+            let value = self.deserialize(value).map_err(|mut e| {
+                e.position += query[..value_start].chars().count();
+                e.key = Some(String::from(key));
+                e
+            })?;
+            result.push((String::from(key), value));
+        }
+        Ok(result)
+    }
+
+    /// Parses jsurl into a stream of [`Event`]s. See [`events`].
+    ///
+    /// [`Deserializer::max_depth`] and [`Deserializer::reject_duplicate_keys`] are enforced,
+    /// the latter by remembering the keys of every open object. Since the input is borrowed
+    /// rather than copied, [`Deserializer::percent_decode`], [`Deserializer::trim`] and
+    /// [`Deserializer::separator`] are not applied; prepare the input beforehand instead.
+    pub fn events<'a>(&'a self, s: &'a str) -> Events<'a> {
+        Events {
+            chars: Reader::new(s, self),
+            stack: Vec::new(),
+            started: false,
+            pending_end: false,
+            done: false,
+            span: 0..0,
+        }
+    }
+
+    /// Creates a [`PushParser`] that uses these options.
+    ///
+    /// [`Deserializer::percent_decode`], [`Deserializer::trim`] and
+    /// [`Deserializer::separator`] are not applied; prepare the input before feeding it
+    /// instead.
+    pub fn push_parser(&self) -> PushParser {
+        PushParser {
+            config: self.clone(),
+            buf: String::new(),
+            fed: 0,
+            dropped: 0,
+            scanned: 0,
+            stack: Vec::new(),
+            depth: 0,
+            expect_value: true,
+            value: None,
+            error: None,
+        }
+    }
+
+    /// Creates a [`ParseCursor`] over `s` that parses with these options. See [`cursor`].
+    ///
+    /// As with [`Deserializer::events`], [`Deserializer::percent_decode`],
+    /// [`Deserializer::trim`] and [`Deserializer::separator`] are not applied.
+    pub fn cursor<'a>(&'a self, s: &'a str) -> ParseCursor<'a> {
+        ParseCursor {
+            chars: Reader::new(s, self),
+        }
+    }
+
+    fn run<T>(
+        &self,
+        s: &str,
+        parse: impl FnOnce(&mut Reader) -> Result<T, DeserializeError>,
+    ) -> Result<T, DeserializeError> {
+        self.check_input_len(s)?;
+        if self.trim {
+            let rest = s.strip_prefix('\u{feff}').unwrap_or(s);
+            let rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+            let skipped = s[..s.len() - rest.len()].chars().count();
+            let trimmed = rest.trim_end_matches(|c: char| c.is_ascii_whitespace());
+            return self.run_untrimmed(trimmed, parse).map_err(|mut e| {
+                e.position += skipped;
+                e
+            });
+        }
+        self.run_untrimmed(s, parse)
+    }
+
+    fn run_untrimmed<T>(
+        &self,
+        s: &str,
+        parse: impl FnOnce(&mut Reader) -> Result<T, DeserializeError>,
+    ) -> Result<T, DeserializeError> {
+        if self.percent_decode && s.contains('%') {
+            let decoded = percent_decode(s)?;
+            return self.run_decoded(str_from_utf8(&decoded)?, parse);
+        }
+        self.run_decoded(s, parse)
+    }
+
+    fn run_decoded<T>(
+        &self,
+        s: &str,
+        parse: impl FnOnce(&mut Reader) -> Result<T, DeserializeError>,
+    ) -> Result<T, DeserializeError> {
+        if self.separator == VALUE_MARKER {
+            return self.parse_with(s, parse);
+        }
+        // both are ASCII, so swapping them leaves every position where it was
+        if let Some(i) = s.find(VALUE_MARKER) {
+            return Err(DeserializeError::new(
+                ErrorKind::UnexpectedChar(VALUE_MARKER),
+                s[..i].chars().count(),
+            ));
+        }
+        let standard = s.replace(self.separator, "~");
+        self.parse_with(&standard, parse).map_err(|mut e| {
+            if e.kind == ErrorKind::UnexpectedChar(VALUE_MARKER) {
+                e.kind = ErrorKind::UnexpectedChar(self.separator);
+            }
+            e
+        })
+    }
+
+    fn check_input_len(&self, s: &str) -> Result<(), DeserializeError> {
+        // the length in bytes is an upper bound of the length in chars, so inputs within
+        // the limit never need to be counted
+        if s.len() > self.max_input_len && s.chars().count() > self.max_input_len {
+            return Err(DeserializeError::new(
+                ErrorKind::InputTooLong,
+                self.max_input_len,
+            ));
+        }
+        Ok(())
+    }
+
+    fn parse_with<T>(
+        &self,
+        s: &str,
+        parse: impl FnOnce(&mut Reader) -> Result<T, DeserializeError>,
+    ) -> Result<T, DeserializeError> {
+        let mut chars = Reader::new(s, self);
+        let result = parse(&mut chars)?;
+        chars.end()?;
+        Ok(result)
+    }
+
+    /// Like [`Deserializer::deserialize`], but skips a leading `?name=` segment. See
+    /// [`deserialize_lenient`].
+    pub fn deserialize_lenient(&self, s: &str) -> Result<serde_json::Value, DeserializeError> {
+        let (prefix, rest) = split_query_prefix(s, self.separator);
+        self.deserialize(rest).map_err(|mut e| {
+            e.position += prefix.chars().count();
+            e
+        })
+    }
+
+    /// Deserializes a value without its leading `~`. See [`deserialize_compact`].
+    pub fn deserialize_compact(&self, s: &str) -> Result<serde_json::Value, DeserializeError> {
+        self.run(s, parse_unmarked)
+    }
+}
+
+/// Deserializes a jsurl string directly into any type implementing `serde::Deserialize`.
+///
+/// This is equivalent to calling [`deserialize`] followed by `serde_json::from_value`.
+///
+/// # Example
+///
+/// ```rust
+/// #[derive(serde::Deserialize)]
+/// struct Config {
+///     name: String,
+///     retries: u32,
+/// }
+///
+/// let cfg: Config = jsurl::deserialize_into("~(name~'primary~retries~3)").unwrap();
+/// assert_eq!(cfg.name, "primary");
+/// assert_eq!(cfg.retries, 3);
+/// ```
+pub fn deserialize_into<T: serde::de::DeserializeOwned>(s: &str) -> Result<T, Error> {
+    let value = deserialize(s)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Checks that `value` survives a trip through [`serialize`] and [`deserialize`] unchanged.
+///
+/// This is intended as a property for fuzzing and other randomized tests; see the `fuzz/`
+/// directory of the repository. Note that a float with an integral value, such as `3.0`,
+/// is written as `3` and so comes back as an integer, which this check reports as a
+/// mismatch; see [`Serializer::preserve_floats`].
+pub fn roundtrip_check(value: &serde_json::Value) -> bool {
+    deserialize(serialize(value)).is_ok_and(|result| result == *value)
+}
+
+/// An error returned by the functions that convert between jsurl and arbitrary serde types.
+///
+/// More variants may be added in minor releases.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The input was not valid jsurl.
+    Deserialize(DeserializeError),
+    /// The value could not be converted to or from the requested type.
+    Json(serde_json::Error),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Deserialize(e) => e.fmt(f),
+            Error::Json(e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Deserialize(e) => Some(e),
+            Error::Json(e) => Some(e),
+        }
+    }
+}
+
+impl From<DeserializeError> for Error {
+    fn from(e: DeserializeError) -> Self {
+        Error::Deserialize(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// A `serde_json::Value` that parses from and displays as jsurl.
+///
+/// Formatting writes the output of [`serialize`] straight to the formatter with
+/// [`serialize_to_writer`], so `format!`, `println!` and logging macros do not build an
+/// intermediate string.
+///
+/// # Example
+///
+/// ```rust
+/// use jsurl::JsUrl;
+/// use serde_json::json;
+///
+/// let value: JsUrl = "~(a~(~1~2))".parse().unwrap();
+/// assert_eq!(value["a"][1], 2);
+/// assert_eq!(value.to_string(), "~(a~(~1~2))");
+///
+/// let state = JsUrl(json!({"q": "rust"}));
+/// assert_eq!(format!("/search?state={}", state), "/search?state=~(q~'rust)");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct JsUrl(pub serde_json::Value);
+
+impl JsUrl {
+    /// Unwraps the inner value.
+    pub fn into_inner(self) -> serde_json::Value {
+        self.0
+    }
+}
+
+impl core::str::FromStr for JsUrl {
+    type Err = DeserializeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        deserialize(s).map(JsUrl)
+    }
+}
+
+impl TryFrom<&str> for JsUrl {
+    type Error = DeserializeError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl TryFrom<String> for JsUrl {
+    type Error = DeserializeError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<serde_json::Value> for JsUrl {
+    fn from(value: serde_json::Value) -> Self {
+        JsUrl(value)
+    }
+}
+
+/// Serializes the value with [`serialize`].
+impl From<JsUrl> for String {
+    fn from(value: JsUrl) -> Self {
+        serialize(&value.0)
+    }
+}
+
+impl core::fmt::Display for JsUrl {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        serialize_to_writer(&self.0, f)
+    }
+}
+
+/// Serializes as its jsurl string.
+#[cfg(feature = "serde_impls")]
+impl serde::Serialize for JsUrl {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserializes from a string, which is parsed with [`deserialize`].
+#[cfg(feature = "serde_impls")]
+impl<'de> serde::Deserialize<'de> for JsUrl {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct JsUrlVisitor;
+
+        impl serde::de::Visitor<'_> for JsUrlVisitor {
+            type Value = JsUrl;
+
+            fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.write_str("a jsurl string")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<JsUrl, E> {
+                s.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(JsUrlVisitor)
+    }
+}
+
+impl core::ops::Deref for JsUrl {
+    type Target = serde_json::Value;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl core::ops::DerefMut for JsUrl {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// The canonical jsurl serialization of a value, as produced by [`serialize_canonical`].
+///
+/// Two `CanonicalJsUrl`s are equal exactly when the values they were built from are equal,
+/// which makes them suitable as `HashMap` or `BTreeMap` keys. They can be looked up by
+/// `&str`, since they implement `Borrow<str>`.
+///
+/// # Example
+///
+/// ```rust
+/// use jsurl::CanonicalJsUrl;
+/// use serde_json::json;
+/// use std::collections::HashMap;
+///
+/// let mut cache = HashMap::new();
+/// cache.insert(CanonicalJsUrl::new(&json!({"a": 1, "b": 2})), "cached");
+///
+/// let key = CanonicalJsUrl::new(&json!({"b": 2, "a": 1}));
+/// assert_eq!(key.as_str(), "~(a~1~b~2)");
+/// assert_eq!(cache[&key], "cached");
+/// assert_eq!(cache["~(a~1~b~2)"], "cached");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CanonicalJsUrl(String);
+
+impl CanonicalJsUrl {
+    /// Serializes `value` with [`serialize_canonical`].
+    pub fn new(value: &serde_json::Value) -> Self {
+        CanonicalJsUrl(serialize_canonical(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl From<&serde_json::Value> for CanonicalJsUrl {
+    fn from(value: &serde_json::Value) -> Self {
+        CanonicalJsUrl::new(value)
+    }
+}
+
+impl core::borrow::Borrow<str> for CanonicalJsUrl {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for CanonicalJsUrl {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl core::fmt::Display for CanonicalJsUrl {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A cursor over the input that can report how far it has advanced, along with the
+/// state needed while parsing.
+///
+/// The cursor is a plain byte offset into the input, so lookahead and position tracking
+/// never have to re-scan or clone anything.
+#[derive(Clone)]
+struct Reader<'a> {
+    input: &'a str,
+    /// The byte offset of the next char to be read, always on a char boundary.
+    pos: usize,
+    config: &'a Deserializer,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str, config: &'a Deserializer) -> Self {
+        Reader {
+            input,
+            pos: 0,
+            config,
+            depth: 0,
+        }
+    }
+
+    /// The byte offset of the next char to be read.
+    fn offset(&self) -> usize {
+        self.pos
+    }
+
+    /// The input that has not been read yet.
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    /// Consumes the next `len` bytes, which must end on a char boundary.
+    fn advance(&mut self, len: usize) {
+        self.pos += len;
+    }
+
+    fn error(&self, kind: ErrorKind) -> DeserializeError {
+        self.error_at(self.offset(), kind)
+    }
+
+    /// Builds an error for the given byte offset. The offset is only converted into a
+    /// char position here so that the happy path never has to count chars.
+    fn error_at(&self, offset: usize, kind: ErrorKind) -> DeserializeError {
+        DeserializeError::new(kind, self.input[..offset].chars().count())
+    }
+
+    /// Records entering an array or object that starts at `offset`.
+    fn enter(&mut self, offset: usize) -> Result<(), DeserializeError> {
+        if self.depth >= self.config.max_depth {
+            return Err(self.error_at(offset, ErrorKind::DepthLimitExceeded));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// An error for whatever is at the current position: either the next char
+    /// or the end of input.
+    fn unexpected(&self) -> DeserializeError {
+        match self.peek() {
+            Some(c) => self.error(ErrorKind::UnexpectedChar(c)),
+            None => self.error(ErrorKind::UnexpectedEof),
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn peek(&self) -> Option<char> {
+        // the structural chars are all ASCII, so most chars peeked at are too
+        match self.input.as_bytes().get(self.pos) {
+            Some(&b) if b.is_ascii() => Some(b as char),
+            Some(_) => self.rest().chars().next(),
+            None => None,
+        }
+    }
+
+    fn peekn(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    /// Checks that the whole input has been consumed.
+    fn end(&self) -> Result<(), DeserializeError> {
+        match self.peek() {
+            Some(_) => Err(self.error(ErrorKind::TrailingCharacters)),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the array or object whose `(` was just read is an array. Array elements each
+    /// start with `~`, except that `~~` starts an object whose first key is empty, since no
+    /// value can start with `~`.
+    fn at_array(&self) -> bool {
+        let rest = self.rest();
+        rest.starts_with('~') && !rest.starts_with("~~")
+    }
+
+    /// Consumes the `)` that closes an array or object, if it is next. With
+    /// [`Deserializer::allow_trailing_comma_style`], a `~` directly before it is consumed too.
+    fn eat_close(&mut self) -> bool {
+        let rest = self.rest();
+        let len = if rest.starts_with(')') {
+            1
+        } else if self.config.allow_trailing_comma_style && rest.starts_with("~)") {
+            2
+        } else {
+            return false;
+        };
+        self.advance(len);
+        true
+    }
+
+    fn eat(&mut self, expected: char) -> Result<(), DeserializeError> {
+        if self.rest().starts_with(expected) {
+            self.advance(expected.len_utf8());
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+}
+
+fn hex_digit_to_value(c: char) -> Option<u32> {
+    match c {
+        '0'..='9' => Some(c as u32 - '0' as u32),
+        'a'..='f' => Some(10 + c as u32 - 'a' as u32),
+        'A'..='F' => Some(10 + c as u32 - 'A' as u32),
+        _ => None,
+    }
+}
+
+fn is_high_surrogate(unit: u32) -> bool {
+    (0xd800..=0xdbff).contains(&unit)
+}
+
+fn is_low_surrogate(unit: u32) -> bool {
+    (0xdc00..=0xdfff).contains(&unit)
+}
+
+/// Reads the next hex digit of an escape. Anything else, including a `~` or `)` that cuts
+/// the escape short, fails with [`ErrorKind::InvalidEscape`] at the offending char, which
+/// is left unread.
+fn next_hex_digit(chars: &mut Reader) -> Result<u32, DeserializeError> {
+    match chars.peek() {
+        None => Err(chars.error(ErrorKind::UnexpectedEof)),
+        Some(c) => {
+            let value =
+                hex_digit_to_value(c).ok_or_else(|| chars.error(ErrorKind::InvalidEscape))?;
+            chars.next();
+            Ok(value)
+        }
+    }
+}
+
+/// Reads the `n` hex digits of an escape as a number.
+fn decode_hex(chars: &mut Reader, n: usize) -> Result<u32, DeserializeError> {
+    (0..n).try_fold(0, |code, _| Ok((code << 4) | next_hex_digit(chars)?))
+}
+
+/// Decodes the four hex digits following a `**` escape. A high surrogate must be
+/// immediately followed by a `**` escape holding the matching low surrogate.
+fn decode_wide_escape(chars: &mut Reader, start: usize) -> Result<char, DeserializeError> {
+    let unit = decode_hex(chars, 4)?;
+    if is_high_surrogate(unit) {
+        let low_start = chars.offset();
+        let low = if chars.rest().starts_with("**") {
+            chars.advance(2);
+            decode_hex(chars, 4).map(Some)
+        } else {
+            Ok(None)
+        };
+        let low = match low {
+            Ok(Some(low)) if is_low_surrogate(low) => low,
+            // leave whatever follows the lone high surrogate to be decoded on its own
+            _ if chars.config.lossy => {
+                chars.pos = low_start;
+                return Ok(char::REPLACEMENT_CHARACTER);
+            }
+            Err(e) => return Err(e),
+            Ok(_) => return Err(chars.error_at(start, ErrorKind::InvalidEscape)),
+        };
+        let code = 0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00);
+        return core::char::from_u32(code)
+            .ok_or_else(|| chars.error_at(start, ErrorKind::InvalidEscape));
+    }
+    // lone low surrogates are rejected here since they are not valid chars
+    match core::char::from_u32(unit) {
+        Some(c) => Ok(c),
+        None if chars.config.lossy => Ok(char::REPLACEMENT_CHARACTER),
+        None => Err(chars.error_at(start, ErrorKind::InvalidEscape)),
+    }
+}
+
+/// Decodes a string or key up to the next `~` or `)`.
+///
+/// Strings without escapes, such as most keys, are returned as a slice of the input, so the
+/// caller can allocate exactly once (or not at all, for a rejected duplicate key).
+fn decode<'a>(chars: &mut Reader<'a>) -> Result<Cow<'a, str>, DeserializeError> {
+    let rest = chars.rest();
+    let len = find_special(rest, chars.config.plus_as_space);
+    if !matches!(rest.as_bytes().get(len), Some(b'*' | b'!' | b'+')) {
+        chars.advance(len);
+        return Ok(Cow::Borrowed(&rest[..len]));
+    }
+    decode_escaped(chars).map(Cow::Owned)
+}
+
+/// The byte offset of the first char in `s` that ends a plain run in a string: `~` or `)`,
+/// which end the string, or `*` or `!`, which start an escape, or `+` with `plus_as_space`.
+/// Returns `s.len()` if there is none.
+///
+/// These are all ASCII, so they can be searched for byte by byte: a byte below 0x80 never
+/// occurs inside a multi-byte char, which makes this much faster than decoding chars.
+fn find_special(s: &str, plus_as_space: bool) -> usize {
+    s.bytes()
+        .position(|b| matches!(b, b'~' | b')' | b'*' | b'!') || (plus_as_space && b == b'+'))
+        .unwrap_or(s.len())
+}
+
+/// How far [`decode_escaped`] looks for the end of a string to size its result.
+const SCAN_AHEAD: usize = 256;
+
+fn decode_escaped(chars: &mut Reader) -> Result<String, DeserializeError> {
+    // every escape is at least as long as the UTF-8 it decodes to (`*xx` is at most two
+    // bytes, `**xxxx` at most three, and a pair of them four), so for a short string the
+    // encoded length is enough to never grow the result; longer ones are not scanned
+    // ahead, since growing them is cheaper than that
+    let rest = &chars.rest().as_bytes()[..chars.rest().len().min(SCAN_AHEAD)];
+    let encoded_len = rest
+        .iter()
+        .position(|&b| b == b'~' || b == b')')
+        .unwrap_or(rest.len());
+    let mut result = String::with_capacity(encoded_len);
+    loop {
+        let c = chars.peek();
+        match c {
+            Some('~') | Some(')') => {
+                return Ok(result);
+            }
+            Some('*') => {
+                let start = chars.offset();
+                chars.next();
+                // case: character with unicode value > 0xff
+                if chars.peek() == Some('*') {
+                    chars.next();
+                    result.push(decode_wide_escape(chars, start)?);
+                } else {
+                    // case: character with unicode value <= 0xff, which is always valid
+                    let code = decode_hex(chars, 2)?;
+                    result.push(char::from(code as u8));
+                }
+            }
+            Some('!') => {
+                result.push('$');
+                chars.next();
+            }
+            Some('+') if chars.config.plus_as_space => {
+                result.push(' ');
+                chars.next();
+            }
+            Some(_) => {
+                // copy the whole run of plain chars up to the next special one at once
+                let rest = chars.rest();
+                let len = find_special(rest, chars.config.plus_as_space);
+                result.push_str(&rest[..len]);
+                chars.advance(len);
+            }
+            None => return Ok(result),
+        }
+    }
+}
+
+fn build_array<B: Builder>(
+    chars: &mut Reader,
+    builder: &mut B,
+) -> Result<B::Value, DeserializeError> {
+    let mut array = builder.begin_array();
+    // handle case where empty array is represented as "~(~)"
+    if let Some(')') = chars.peekn(1) {
+        chars.eat('~')?;
+        chars.eat(')')?;
+        return Ok(builder.end_array(array));
+    }
+    loop {
+        if chars.eat_close() {
+            return Ok(builder.end_array(array));
+        }
+        let value = build_one(chars, builder)?;
+        builder.push(&mut array, value);
+    }
+}
+
+fn build_object<'a, B: Builder>(
+    chars: &mut Reader<'a>,
+    builder: &mut B,
+) -> Result<B::Value, DeserializeError> {
+    let mut object = builder.begin_object();
+    // only filled with `reject_duplicate_keys`, since the builder's object cannot be asked
+    let mut keys = alloc::collections::BTreeSet::new();
+    loop {
+        let c = chars
+            .peek()
+            .ok_or_else(|| chars.error(ErrorKind::UnexpectedEof))?;
+        if chars.eat_close() {
+            return Ok(builder.end_object(object));
+        }
+        if c == '~' {
+            chars.next();
+        }
+        let key_start = chars.offset();
+        let key = decode(chars)?;
+        if chars.config.reject_duplicate_keys && !keys.insert(key.clone()) {
+            return Err(chars.error_at(key_start, ErrorKind::DuplicateKey));
+        }
+        let value = build_one(chars, builder)?;
+        builder.insert(&mut object, key, value);
+        if let Some(c) = chars.peek().filter(|&c| c != '~' && c != ')') {
+            return Err(chars.error(ErrorKind::UnexpectedChar(c)));
+        }
+    }
+}
+
+/// Somewhere to put the entries of an object while parsing it.
+trait Entries: Default {
+    fn contains_key(&self, key: &str) -> bool;
+    fn insert(&mut self, key: String, value: serde_json::Value);
+}
+
+impl Entries for Vec<(String, serde_json::Value)> {
+    fn contains_key(&self, key: &str) -> bool {
+        self.iter().any(|(k, _)| k == key)
+    }
+
+    fn insert(&mut self, key: String, value: serde_json::Value) {
+        self.push((key, value));
+    }
+}
+
+/// Parses the entries of an object whose opening `(` has already been consumed.
+fn parse_entries<M: Entries>(chars: &mut Reader) -> Result<M, DeserializeError> {
+    let mut map = M::default();
+    loop {
+        let c = chars
+            .peek()
+            .ok_or_else(|| chars.error(ErrorKind::UnexpectedEof))?;
+        if chars.eat_close() {
+            break;
+        }
+        if c == '~' {
+            chars.next();
+        }
+        let key_start = chars.offset();
+        let key = decode(chars)?;
+        if chars.config.reject_duplicate_keys && map.contains_key(&key) {
+            return Err(chars.error_at(key_start, ErrorKind::DuplicateKey));
+        }
+        let value = parse_one(chars)?;
+        map.insert(key.into_owned(), value);
+        if let Some(c) = chars.peek().filter(|&c| c != '~' && c != ')') {
+            return Err(chars.error(ErrorKind::UnexpectedChar(c)));
+        }
+    }
+    Ok(map)
+}
+
+/// Parses a top-level object into its entries, in input order.
+fn parse_object_entries(
+    chars: &mut Reader,
+) -> Result<Vec<(String, serde_json::Value)>, DeserializeError> {
+    chars.eat('~')?;
+    let start = chars.offset();
+    match chars.next() {
+        Some('(') => {
+            if chars.at_array() {
+                return Err(chars.unexpected());
+            }
+            chars.enter(start)?;
+            let result = parse_entries(chars);
+            chars.leave();
+            result
+        }
+        Some(c) => Err(chars.error_at(start, ErrorKind::UnexpectedChar(c))),
+        None => Err(chars.error(ErrorKind::UnexpectedEof)),
+    }
+}
+
+fn parse_one(chars: &mut Reader) -> Result<serde_json::Value, DeserializeError> {
+    build_one(chars, &mut ValueBuilder)
+}
+
+/// Parses a value whose leading `~` has already been consumed, or was left out by
+/// [`serialize_compact`].
+fn parse_unmarked(chars: &mut Reader) -> Result<serde_json::Value, DeserializeError> {
+    build_unmarked(chars, &mut ValueBuilder)
+}
+
+fn build_one<B: Builder>(
+    chars: &mut Reader,
+    builder: &mut B,
+) -> Result<B::Value, DeserializeError> {
+    chars.eat('~')?;
+    build_unmarked(chars, builder)
+}
+
+fn build_unmarked<B: Builder>(
+    chars: &mut Reader,
+    builder: &mut B,
+) -> Result<B::Value, DeserializeError> {
+    let start = chars.offset();
+    match chars.next() {
+        Some('(') => {
+            chars.enter(start)?;
+            let result = if chars.at_array() {
+                build_array(chars, builder)
+            } else {
+                build_object(chars, builder)
+            };
+            chars.leave();
+            result
+        }
+        Some('\'') => Ok(builder.build_string(decode(chars)?)),
+        Some(_) => build_token(chars, start, builder),
+        None => Err(chars.error(ErrorKind::UnexpectedEof)),
+    }
+}
+
+/// Checks a value the same way [`parse_one`] reads it, but without building it.
+fn skip_one(chars: &mut Reader) -> Result<(), DeserializeError> {
+    chars.eat('~')?;
+    let start = chars.offset();
+    match chars.next() {
+        Some('(') => {
+            chars.enter(start)?;
+            let result = if chars.at_array() {
+                skip_array(chars)
+            } else {
+                skip_object(chars)
+            };
+            chars.leave();
+            result
+        }
+        Some('\'') => decode(chars).map(drop),
+        Some(_) => parse_token(chars, start).map(drop),
+        None => Err(chars.error(ErrorKind::UnexpectedEof)),
+    }
+}
+
+fn skip_array(chars: &mut Reader) -> Result<(), DeserializeError> {
+    // handle case where empty array is represented as "~(~)"
+    if let Some(')') = chars.peekn(1) {
+        chars.eat('~')?;
+        chars.eat(')')?;
+        return Ok(());
+    }
+    loop {
+        if chars.eat_close() {
+            return Ok(());
+        }
+        skip_one(chars)?;
+    }
+}
+
+fn skip_object(chars: &mut Reader) -> Result<(), DeserializeError> {
+    let mut keys = alloc::collections::BTreeSet::new();
+    loop {
+        let c = chars
+            .peek()
+            .ok_or_else(|| chars.error(ErrorKind::UnexpectedEof))?;
+        if chars.eat_close() {
+            return Ok(());
+        }
+        if c == '~' {
+            chars.next();
+        }
+        let key_start = chars.offset();
+        let key = decode(chars)?;
+        if chars.config.reject_duplicate_keys && !keys.insert(key) {
+            return Err(chars.error_at(key_start, ErrorKind::DuplicateKey));
+        }
+        skip_one(chars)?;
+        if let Some(c) = chars.peek().filter(|&c| c != '~' && c != ')') {
+            return Err(chars.error(ErrorKind::UnexpectedChar(c)));
+        }
+    }
+}
+
+/// Parses a bare token (a number, `null`, `true`, or `false`) whose first char, at byte
+/// offset `start`, has already been consumed.
+fn parse_token(chars: &mut Reader, start: usize) -> Result<serde_json::Value, DeserializeError> {
+    build_token(chars, start, &mut ValueBuilder)
+}
+
+fn build_token<B: Builder>(
+    chars: &mut Reader,
+    start: usize,
+    builder: &mut B,
+) -> Result<B::Value, DeserializeError> {
+    match read_token(chars, start)? {
+        Token::Null => Ok(builder.build_null()),
+        Token::Bool(b) => Ok(builder.build_bool(b)),
+        Token::Number(token) if chars.config.numbers_as_strings => {
+            Ok(builder.build_string(Cow::Borrowed(token)))
+        }
+        // the parse can still fail after the grammar check, as for floats out of range
+        Token::Number(token) => builder
+            .build_number(token)
+            .ok_or_else(|| chars.error_at(start, ErrorKind::InvalidNumber)),
+    }
+}
+
+/// A bare token, with numbers still as their text.
+enum Token<'a> {
+    Null,
+    Bool(bool),
+    Number(&'a str),
+}
+
+/// Reads a bare token whose first char, at byte offset `start`, has already been consumed,
+/// checking numbers against the grammar and [`Deserializer::max_number_len`].
+fn read_token<'a>(chars: &mut Reader<'a>, start: usize) -> Result<Token<'a>, DeserializeError> {
+    // a `~` or `)` here means the value is missing entirely, as in `~~` or `~(a~)`
+    if let Some(c @ ('~' | ')')) = chars.input[start..].chars().next() {
+        return Err(chars.error_at(start, ErrorKind::UnexpectedChar(c)));
+    }
+    // the token is sliced out of the input in one go, however long it is, and searched for
+    // byte by byte as in `find_special`
+    let rest = chars.rest();
+    let len = rest
+        .bytes()
+        .position(|b| b == b'~' || b == b')')
+        .unwrap_or(rest.len());
+    chars.advance(len);
+    let input = chars.input;
+    let token = &input[start..chars.offset()];
+    match token {
+        "null" => return Ok(Token::Null),
+        "true" => return Ok(Token::Bool(true)),
+        "false" => return Ok(Token::Bool(false)),
+        _ => {}
+    }
+    match token.chars().next() {
+        // the grammar is checked up front rather than left to the builder, so that what is
+        // accepted does not depend on its parser
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let max_len = chars.config.max_number_len;
+            if token.len() > max_len && token.chars().count() > max_len {
+                return Err(chars.error_at(start, ErrorKind::NumberTooLong));
+            }
+            if !is_number_token(token) {
+                return Err(chars.error_at(start, ErrorKind::InvalidNumber));
+            }
+            Ok(Token::Number(token))
+        }
+        _ => Err(chars.error_at(start, ErrorKind::InvalidLiteral)),
+    }
+}
+
+/// Whether `token` matches the JSON number grammar,
+/// `-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?`.
+fn is_number_token(token: &str) -> bool {
+    fn digits(bytes: &[u8]) -> usize {
+        bytes.iter().take_while(|b| b.is_ascii_digit()).count()
+    }
+    let bytes = token.as_bytes();
+    let bytes = bytes.strip_prefix(b"-").unwrap_or(bytes);
+    let int = if bytes.first() == Some(&b'0') {
+        1
+    } else {
+        digits(bytes)
+    };
+    if int == 0 {
+        return false;
+    }
+    let mut bytes = &bytes[int..];
+    if let [b'.', rest @ ..] = bytes {
+        let frac = digits(rest);
+        if frac == 0 {
+            return false;
+        }
+        bytes = &rest[frac..];
+    }
+    if let [b'e' | b'E', rest @ ..] = bytes {
+        let rest = rest
+            .strip_prefix(b"+")
+            .or_else(|| rest.strip_prefix(b"-"))
+            .unwrap_or(rest);
+        let exp = digits(rest);
+        if exp == 0 {
+            return false;
+        }
+        bytes = &rest[exp..];
+    }
+    bytes.is_empty()
+}
+
+/// A partially built container on the stack of the iterative parser.
+enum Frame {
+    Array(Vec<serde_json::Value>),
+    /// An object and the key whose value is currently being parsed.
+    Object(serde_json::Map<String, serde_json::Value>, String),
+}
+
+impl Frame {
+    fn into_value(self) -> serde_json::Value {
+        match self {
+            Frame::Array(items) => serde_json::Value::Array(items),
+            Frame::Object(map, _) => serde_json::Value::Object(map),
+        }
+    }
+}
+
+/// Equivalent to [`parse_one`], but keeps track of open containers on the heap rather
+/// than recursing, so arbitrarily deep input cannot overflow the stack.
+fn parse_one_iterative(chars: &mut Reader) -> Result<serde_json::Value, DeserializeError> {
+    let mut stack = Vec::new();
+    loop {
+        let mut value = match begin_value(chars, &mut stack)? {
+            Some(value) => value,
+            None => match advance_frame(chars, &mut stack)? {
+                Some(value) => value,
+                None => continue,
+            },
+        };
+        // hand the finished value to its parent, which may in turn be finished
+        loop {
+            match stack.last_mut() {
+                None => return Ok(value),
+                Some(Frame::Array(items)) => items.push(value),
+                Some(Frame::Object(map, key)) => {
+                    map.insert(core::mem::take(key), value);
+                    if let Some(c) = chars.peek().filter(|&c| c != '~' && c != ')') {
+                        return Err(chars.error(ErrorKind::UnexpectedChar(c)));
+                    }
+                }
+            }
+            match advance_frame(chars, &mut stack)? {
+                Some(finished) => value = finished,
+                None => break,
+            }
+        }
+    }
+}
+
+/// Reads the start of a value. Scalars and empty arrays are returned directly, while any
+/// other container is pushed onto the stack and `None` is returned.
+fn begin_value(
+    chars: &mut Reader,
+    stack: &mut Vec<Frame>,
+) -> Result<Option<serde_json::Value>, DeserializeError> {
+    chars.eat('~')?;
+    let start = chars.offset();
+    match chars.next() {
+        Some('(') => {
+            chars.enter(start)?;
+            if chars.at_array() {
+                // handle case where empty array is represented as "~(~)"
+                if let Some(')') = chars.peekn(1) {
+                    chars.eat('~')?;
+                    chars.eat(')')?;
+                    chars.leave();
+                    return Ok(Some(serde_json::Value::Array(Vec::new())));
+                }
+                stack.push(Frame::Array(Vec::new()));
+            } else {
+                stack.push(Frame::Object(serde_json::Map::new(), String::new()));
+            }
+            Ok(None)
+        }
+        Some('\'') => Ok(Some(serde_json::Value::String(decode(chars)?.into_owned()))),
+        Some(_) => parse_token(chars, start).map(Some),
+        None => Err(chars.error(ErrorKind::UnexpectedEof)),
+    }
+}
+
+/// Moves the innermost open container forward to the point where it either expects its
+/// next value, or is finished. A finished container is popped and returned.
+fn advance_frame(
+    chars: &mut Reader,
+    stack: &mut Vec<Frame>,
+) -> Result<Option<serde_json::Value>, DeserializeError> {
+    let finished = match stack.last_mut() {
+        Some(Frame::Array(_)) => chars.eat_close(),
+        Some(Frame::Object(map, key)) => match chars.peek() {
+            None => return Err(chars.error(ErrorKind::UnexpectedEof)),
+            Some(_) if chars.eat_close() => true,
+            Some(c) => {
+                if c == '~' {
+                    chars.next();
+                }
+                let key_start = chars.offset();
+                *key = decode(chars)?.into_owned();
+                if chars.config.reject_duplicate_keys && map.contains_key(key.as_str()) {
+                    return Err(chars.error_at(key_start, ErrorKind::DuplicateKey));
+                }
+                false
+            }
+        },
+        None => false,
+    };
+    if !finished {
+        return Ok(None);
+    }
+    chars.leave();
+    Ok(stack.pop().map(Frame::into_value))
+}
+
+/// Parses jsurl that arrives in pieces, such as a query string read chunk by chunk,
+/// created by [`PushParser::new`] or [`Deserializer::push_parser`].
+///
+/// Input is parsed as soon as it is known to be complete and then dropped, so apart from
+/// the value being built, only the string or token currently being read is buffered.
+/// Chunks may split the input anywhere, even in the middle of an escape.
+///
+/// ```
+/// use serde_json::json;
+///
+/// let mut parser = jsurl::PushParser::new();
+/// for chunk in ["~(name~'Jo*2", "0hn~tags~(~'a~'b", "))"] {
+///     parser.feed(chunk)?;
+/// }
+/// assert_eq!(parser.finish()?, json!({"name": "Jo hn", "tags": ["a", "b"]}));
+/// # Ok::<(), jsurl::DeserializeError>(())
+/// ```
+pub struct PushParser {
+    config: Deserializer,
+    /// Input that has been fed, but not parsed yet.
+    buf: String,
+    /// The number of chars fed so far.
+    fed: usize,
+    /// The number of chars that have been parsed and dropped from `buf`.
+    dropped: usize,
+    /// The byte offset in `buf` up to which the last search found no `~` or `)`.
+    scanned: usize,
+    stack: Vec<Frame>,
+    depth: usize,
+    /// Whether the next step reads a value, rather than advancing the innermost container.
+    expect_value: bool,
+    value: Option<serde_json::Value>,
+    error: Option<DeserializeError>,
+}
+
+impl PushParser {
+    /// Creates a parser with the default [`Deserializer`] options.
+    pub fn new() -> Self {
+        DEFAULT_DESERIALIZER.push_parser()
+    }
+
+    /// Parses as much of the input fed so far, including `chunk`, as is complete.
+    ///
+    /// Errors are reported as soon as they are found. Once an error has been returned, it
+    /// is returned again by every later call to [`PushParser::feed`] or
+    /// [`PushParser::finish`].
+    pub fn feed(&mut self, chunk: &str) -> Result<(), DeserializeError> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+        self.fed += chunk.chars().count();
+        if self.fed > self.config.max_input_len {
+            let e = DeserializeError::new(ErrorKind::InputTooLong, self.config.max_input_len);
+            self.error = Some(e.clone());
+            return Err(e);
+        }
+        self.buf.push_str(chunk);
+        self.parse(false)
+            .inspect_err(|e| self.error = Some(e.clone()))
+    }
+
+    /// Parses the rest of the input and returns its value, failing if the input fed so far
+    /// is not a complete jsurl value.
+    pub fn finish(mut self) -> Result<serde_json::Value, DeserializeError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.parse(true)?;
+        Ok(self
+            .value
+            .expect("a successful parse always produces a value"))
+    }
+
+    /// Runs the steps of the iterative parser for as long as the input they read is
+    /// complete, which is always the case once `at_end` is set.
+    fn parse(&mut self, at_end: bool) -> Result<(), DeserializeError> {
+        let mut chars = Reader {
+            input: &self.buf,
+            pos: 0,
+            config: &self.config,
+            depth: self.depth,
+        };
+        let result = loop {
+            if self.value.is_some() {
+                break chars.end();
+            }
+            if !at_end {
+                // a step reads at most four chars before the string or token it ends with,
+                // and every string or token is followed by a `~` or `)`, so any such char
+                // past those four means the step cannot run out of input
+                let from = self.scanned.max(chars.offset() + 4).min(self.buf.len());
+                if !self.buf.as_bytes()[from..]
+                    .iter()
+                    .any(|&b| b == b'~' || b == b')')
+                {
+                    self.scanned = self.buf.len();
+                    break Ok(());
+                }
+            }
+            let step = if self.expect_value {
+                begin_value(&mut chars, &mut self.stack)
+            } else {
+                advance_frame(&mut chars, &mut self.stack)
+            };
+            let value = match step {
+                Ok(Some(value)) => value,
+                Ok(None) => {
+                    self.expect_value = !self.expect_value;
+                    continue;
+                }
+                Err(e) => break Err(e),
+            };
+            // mirrors `parse_one_iterative`
+            match self.stack.last_mut() {
+                None => self.value = Some(value),
+                Some(Frame::Array(items)) => items.push(value),
+                Some(Frame::Object(map, key)) => {
+                    map.insert(core::mem::take(key), value);
+                    if let Some(c) = chars.peek().filter(|&c| c != '~' && c != ')') {
+                        break Err(chars.error(ErrorKind::UnexpectedChar(c)));
+                    }
+                }
+            }
+            self.expect_value = false;
+        };
+        self.depth = chars.depth;
+        let parsed = chars.offset();
+        let dropped = self.dropped;
+        self.dropped += self.buf[..parsed].chars().count();
+        self.buf.drain(..parsed);
+        self.scanned = self.scanned.saturating_sub(parsed);
+        result.map_err(|mut e| {
+            e.position += dropped;
+            e
+        })
+    }
+}
+
+impl Default for PushParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for PushParser {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PushParser")
+            .field("offset", &self.dropped)
+            .field("buffered", &self.buf.len())
+            .field("depth", &self.stack.len())
+            .finish()
+    }
+}
+
+/// A token produced by [`events`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The start of an object. It is followed by alternating [`Event::Key`]s and values, and
+    /// closed by [`Event::End`].
+    BeginObject,
+    /// An object key. The next value belongs to it.
+    Key(String),
+    /// The start of an array. It is followed by its values, and closed by [`Event::End`].
+    BeginArray,
+    String(String),
+    Number(serde_json::Number),
+    Bool(bool),
+    Null,
+    /// The end of the innermost open array or object.
+    End,
+}
+
+/// An iterator over the [`Event`]s of a jsurl string, created by [`events`] or
+/// [`Deserializer::events`].
+pub struct Events<'a> {
+    chars: Reader<'a>,
+    stack: Vec<Container>,
+    started: bool,
+    /// Set after an empty array, whose `BeginArray` and `End` are read in one go.
+    pending_end: bool,
+    done: bool,
+    /// The byte range of the last event in the input.
+    span: core::ops::Range<usize>,
+}
+
+/// An open container of the event parser.
+enum Container {
+    Array,
+    Object {
+        /// Whether no entry has been read yet.
+        first: bool,
+        /// Whether a key has been read, but not its value.
+        expect_value: bool,
+        /// The keys read so far, only tracked when rejecting duplicate keys.
+        keys: alloc::collections::BTreeSet<String>,
+    },
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event, DeserializeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.advance();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+impl core::fmt::Debug for Events<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Events")
+            .field("offset", &self.chars.offset())
+            .field("depth", &self.stack.len())
+            .finish()
+    }
+}
+
+impl Events<'_> {
+    /// The byte range in the input of the last event returned by [`Iterator::next`].
+    ///
+    /// For an [`Event::Key`] or [`Event::String`], this is the key or string as it appears
+    /// in the input, without the `~` or `~'` before it. If that range contains no `*` or
+    /// `!`, it holds no escapes, and is byte for byte the UTF-8 of the decoded text, so the
+    /// input can be sliced instead of using the event's owned copy. For a number, bool or
+    /// null it is the token, for [`Event::BeginArray`] and [`Event::BeginObject`] the `(`,
+    /// and for [`Event::End`] the closing `)`, along with any `~` consumed with it.
+    ///
+    /// ```rust
+    /// let input = "~(name~'caf\u{e9}~tag~'a*20b)";
+    /// let mut events = jsurl::events(input);
+    /// let mut strings = Vec::new();
+    /// while let Some(event) = events.next() {
+    ///     if let jsurl::Event::String(_) = event.unwrap() {
+    ///         strings.push(&input.as_bytes()[events.span()]);
+    ///     }
+    /// }
+    /// assert_eq!(strings, [&b"caf\xc3\xa9"[..], b"a*20b"]);
+    /// ```
+    pub fn span(&self) -> core::ops::Range<usize> {
+        self.span.clone()
+    }
+
+    fn advance(&mut self) -> Option<Result<Event, DeserializeError>> {
+        if self.pending_end {
+            self.pending_end = false;
+            // the `~)` after the `(` of the `BeginArray`
+            self.span = self.span.end..self.chars.offset();
+            return Some(Ok(Event::End));
+        }
+        let start = self.chars.offset();
+        let chars = &mut self.chars;
+        match self.stack.last_mut() {
+            None if self.started => return chars.end().err().map(Err),
+            None => {
+                if let Err(e) = chars.config.check_input_len(chars.input) {
+                    return Some(Err(e));
+                }
+                self.started = true;
+            }
+            Some(Container::Array) => {
+                if chars.eat_close() {
+                    return Some(Ok(self.close(start)));
+                }
+            }
+            Some(Container::Object {
+                first,
+                expect_value,
+                keys,
+            }) => {
+                if *expect_value {
+                    *expect_value = false;
+                    *first = false;
+                } else {
+                    // mirrors `parse_object`
+                    let c = chars.peek();
+                    if let Some(c) = c.filter(|&c| !*first && c != '~' && c != ')') {
+                        return Some(Err(chars.error(ErrorKind::UnexpectedChar(c))));
+                    }
+                    if c.is_none() {
+                        return Some(Err(chars.error(ErrorKind::UnexpectedEof)));
+                    }
+                    if chars.eat_close() {
+                        return Some(Ok(self.close(start)));
+                    }
+                    if c == Some('~') {
+                        chars.next();
+                    }
+                    let key_start = chars.offset();
+                    let key = match decode(chars) {
+                        Ok(key) => key.into_owned(),
+                        Err(e) => return Some(Err(e)),
+                    };
+                    if chars.config.reject_duplicate_keys && !keys.insert(key.clone()) {
+                        return Some(Err(chars.error_at(key_start, ErrorKind::DuplicateKey)));
+                    }
+                    *expect_value = true;
+                    self.span = key_start..chars.offset();
+                    return Some(Ok(Event::Key(key)));
+                }
+            }
+        }
+        Some(self.begin_value())
+    }
+
+    /// Closes the innermost container, whose closing `)` started at byte offset `start`.
+    fn close(&mut self, start: usize) -> Event {
+        self.chars.leave();
+        self.stack.pop();
+        self.span = start..self.chars.offset();
+        Event::End
+    }
+
+    /// Reads a value, or the start of a container.
+    fn begin_value(&mut self) -> Result<Event, DeserializeError> {
+        let chars = &mut self.chars;
+        chars.eat('~')?;
+        let start = chars.offset();
+        self.span = start..start + 1;
+        match chars.next() {
+            Some('(') => {
+                chars.enter(start)?;
+                if chars.at_array() {
+                    // handle case where empty array is represented as "~(~)"
+                    if let Some(')') = chars.peekn(1) {
+                        chars.eat('~')?;
+                        chars.eat(')')?;
+                        chars.leave();
+                        self.pending_end = true;
+                    } else {
+                        self.stack.push(Container::Array);
+                    }
+                    Ok(Event::BeginArray)
+                } else {
+                    self.stack.push(Container::Object {
+                        first: true,
+                        expect_value: false,
+                        keys: alloc::collections::BTreeSet::new(),
+                    });
+                    Ok(Event::BeginObject)
+                }
+            }
+            Some('\'') => {
+                let value = decode(chars)?.into_owned();
+                self.span = start + 1..chars.offset();
+                Ok(Event::String(value))
+            }
+            Some(_) => {
+                let token = read_token(chars, start)?;
+                self.span = start..chars.offset();
+                Ok(match token {
+                    Token::Null => Event::Null,
+                    Token::Bool(b) => Event::Bool(b),
+                    Token::Number(token) if chars.config.numbers_as_strings => {
+                        Event::String(String::from(token))
+                    }
+                    Token::Number(token) => Event::Number(
+                        number_from_token(token)
+                            .ok_or_else(|| chars.error_at(start, ErrorKind::InvalidNumber))?,
+                    ),
+                })
+            }
+            None => Err(chars.error(ErrorKind::UnexpectedEof)),
+        }
+    }
+}
+
+/// A position in a jsurl input, from which values can be parsed one at a time, created by
+/// [`cursor`] or [`Deserializer::cursor`].
+///
+/// Unlike [`deserialize`], parsing a value does not require it to make up the rest of the
+/// input, so whatever follows can be inspected and skipped before parsing the next one.
+/// Cloning a cursor is cheap and gives an independent position, for example to retry a
+/// parse in a different way.
+///
+/// An array or object ends at its closing `)`, but a string or bare token extends to the
+/// next `~` or `)`. So in `~'a&~'b`, the first string is `a&`.
+#[derive(Clone)]
+pub struct ParseCursor<'a> {
+    chars: Reader<'a>,
+}
+
+impl<'a> ParseCursor<'a> {
+    /// Parses the value that starts at the cursor, and moves the cursor past it.
+    ///
+    /// On error, the cursor is left somewhere inside the value. The error's position refers
+    /// to the whole input, not just the part that was remaining.
+    pub fn parse_value(&mut self) -> Result<serde_json::Value, DeserializeError> {
+        self.chars.config.check_input_len(self.chars.input)?;
+        // a previous parse may have failed in the middle of a container
+        self.chars.depth = 0;
+        parse_one(&mut self.chars)
+    }
+
+    /// The input after the cursor.
+    pub fn remaining(&self) -> &'a str {
+        self.chars.rest()
+    }
+
+    /// The char after the cursor, without moving past it.
+    pub fn peek_char(&self) -> Option<char> {
+        self.chars.peek()
+    }
+
+    /// Moves the cursor past the next char and returns it.
+    pub fn next_char(&mut self) -> Option<char> {
+        self.chars.next()
+    }
+
+    /// The byte offset of the cursor into the input.
+    pub fn offset(&self) -> usize {
+        self.chars.offset()
+    }
+}
+
+impl core::fmt::Debug for ParseCursor<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ParseCursor")
+            .field("offset", &self.chars.offset())
+            .field("remaining", &self.remaining())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[macro_export]
+    macro_rules! assert_serialize_eq {
+        ($json_str:expr, $expected:expr $(,)?) => {
+            let obj: serde_json::Value = serde_json::from_str($json_str).expect("invalid JSON");
+            let serialized = serialize(&obj);
+            assert_eq!(serialized, $expected);
+        };
+    }
+
+    #[macro_export]
+    macro_rules! assert_deserialize_eq {
+        ($expected:expr, $jsurl_str:expr $(,)?) => {
+            let obj: serde_json::Value = serde_json::from_str($expected).expect("invalid JSON");
+            let deserialized = deserialize($jsurl_str).expect("invalid jsurl");
+            assert_eq!(deserialized, obj);
+        };
+    }
+
+    #[test]
+    fn reserved_chars_are_always_escaped() {
+        let escaped = ["~'*7e", "~'*28", "~'*29", "~'*27", "~'*2a", "~'*21"];
+        for (c, expected) in RESERVED.into_iter().zip(escaped) {
+            assert!(is_reserved(c));
+            assert_eq!(serialize(&serde_json::json!(c.to_string())), expected);
+        }
+        assert!(!is_reserved('$'));
+        assert!(!is_reserved('-'));
+    }
+
+    #[test]
+    fn serialize_basic_values() {
+        assert_serialize_eq!("null", "~null");
+        assert_serialize_eq!("true", "~true");
+        assert_serialize_eq!("false", "~false");
+        assert_serialize_eq!("0", "~0");
+        assert_serialize_eq!("1", "~1");
+        assert_serialize_eq!("-1.5", "~-1.5");
+
+        // note: Infinity, -Infinity, and NaN are not valid JSON numbers
+        // so they cannot be represented with serde_json::Value
+    }
+
+    #[test]
+    fn serialize_strings() {
+        let s1 = serde_json::Value::String("hello world\u{203c}".to_string());
+        assert_eq!(serialize(&s1), "~'hello*20world**203c");
+
+        let s2 = serde_json::Value::String(" !\"#$%&'()*+,-./09:;<=>?@AZ[\\]^_`az{|}~".to_string());
+        assert_eq!(serialize(&s2), "~'*20*21*22*23!*25*26*27*28*29*2a*2b*2c-.*2f09*3a*3b*3c*3d*3e*3f*40AZ*5b*5c*5d*5e_*60az*7b*7c*7d*7e");
+
+        let s3 = serde_json::Value::String("".to_string());
+        assert_eq!(serialize(&s3), "~'");
+    }
+
+    #[test]
+    fn serialize_arrays() {
+        assert_serialize_eq!("[]", "~(~)");
+        assert_serialize_eq!("[1,2,3]", "~(~1~2~3)");
+        assert_serialize_eq!(
+            r#"[null, false, 0, "hello world\u203c"]"#,
+            "~(~null~false~0~'hello*20world**203c)"
+        );
+    }
+
+    #[test]
+    fn serialize_objects() {
+        assert_serialize_eq!("{}", "~()");
+        assert_serialize_eq!(
+            r#"{"c":null,"d":false,"e":0,"f":"hello world\u203c"}"#,
+            "~(c~null~d~false~e~0~f~'hello*20world**203c)"
+        );
+        assert_serialize_eq!(
+            r#"{"a": [[1, 2],[], {}],"b": [],"c": {"d": "hello","e": {},"f": []}}"#,
+            "~(a~(~(~1~2)~(~)~())~b~(~)~c~(d~'hello~e~()~f~(~)))",
+        );
+    }
+
+    #[test]
+    fn serialize_sorted_keys() {
+        let obj: serde_json::Value =
+            serde_json::from_str(r#"{"b":1,"a":2,"c":{"z":null,"y":[{"n":1,"m":2}]}}"#).unwrap();
+        #[cfg(feature = "std")]
+        assert_eq!(serialize(&obj), "~(b~1~a~2~c~(z~null~y~(~(n~1~m~2))))");
+        assert_eq!(
+            Serializer::new().sort_keys(true).serialize(&obj),
+            "~(a~2~b~1~c~(y~(~(m~2~n~1))~z~null))"
+        );
+    }
+
+    #[test]
+    fn serialize_canonical_equal_values() {
+        let pairs = [
+            (
+                serde_json::json!({"a": 1, "b": 2}),
+                serde_json::json!({"b": 2, "a": 1}),
+            ),
+            (
+                serde_json::json!([{"y": [{"q": 1, "p": 2}], "x": null}]),
+                serde_json::json!([{"x": null, "y": [{"p": 2, "q": 1}]}]),
+            ),
+            (serde_json::json!(7u64), serde_json::json!(7i64)),
+            #[cfg(not(feature = "arbitrary_precision"))]
+            (serde_json::json!(-0.0), serde_json::json!(0.0)),
+        ];
+        for (a, b) in pairs.iter() {
+            assert_eq!(a, b);
+            assert_eq!(serialize_canonical(a), serialize_canonical(b));
+        }
+        assert_eq!(
+            serialize_canonical(&pairs[1].0),
+            "~(~(x~null~y~(~(p~2~q~1))))"
+        );
+        for (a, b) in pairs.iter() {
+            assert_eq!(compare_canonical(a, b), core::cmp::Ordering::Equal);
+        }
+    }
+
+    #[test]
+    fn compare_canonical_orders_by_canonical_form() {
+        let values = [
+            serde_json::json!({"c": [1, 2], "a": null}),
+            serde_json::json!({"a": null, "c": [1]}),
+            serde_json::json!("b"),
+            serde_json::json!(true),
+            serde_json::json!([]),
+            serde_json::json!(-1),
+            serde_json::json!(0.5),
+        ];
+        for a in values.iter() {
+            for b in values.iter() {
+                let expected = serialize_canonical(a).cmp(&serialize_canonical(b));
+                assert_eq!(compare_canonical(a, b), expected);
+                assert_eq!(compare_canonical(b, a), expected.reverse());
+            }
+        }
+        assert_eq!(
+            compare_canonical(&values[0], &values[1]),
+            core::cmp::Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn serialize_readable() {
+        let readable = Serializer::new().readable(true);
+        let value = serde_json::json!({
+            "a b": "hello world",
+            "punct": " !\"#$%&'()*+,-./09:;<=>?@AZ[\\]^_`az{|}~",
+            "unicode": "caf\u{e9} \u{1f600}",
+            "control": "tab\there",
+        });
+        let serialized = readable.serialize(&value);
+        #[cfg(feature = "std")]
+        assert_eq!(
+            serialized,
+            "~(a b~'hello world~punct~' *21\"#!%&'(*29*2a+,-./09:;<=>?@AZ[\\]^_`az{|}*7e\
+             ~unicode~'caf\u{e9} \u{1f600}~control~'tab*09here)"
+        );
+        assert_eq!(deserialize(&serialized).unwrap(), value);
+        assert_eq!(deserialize_iterative(&serialized).unwrap(), value);
+    }
+
+    #[test]
+    fn serialize_safe_chars() {
+        let value = serde_json::json!(["a@b:c", "(x'y)", "~*!$", "\u{e9}"]);
+        let serializer = Serializer::new()
+            .safe_chars("@:")
+            .safe_chars("('~)*!$\u{e9}");
+        let serialized = serializer.serialize(&value);
+        assert_eq!(serialized, "~(~'a@b:c~'(x'y*29~'*7e*2a*21!~'\u{e9})");
+        assert_eq!(deserialize(&serialized).unwrap(), value);
+        assert_eq!(
+            serialize(&value),
+            "~(~'a*40b*3ac~'*28x*27y*29~'*7e*2a*21!~'*e9)"
+        );
+    }
+
+    #[test]
+    fn serialize_url_context() {
+        let value = serde_json::json!({"k&=/": " !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~"});
+        let cases = [
+            (
+                UrlContext::Query,
+                "~(k*26*3d/~'*20*21*22*23!*25*26'(*29*2a*2b,-./:*3b*3c*3d*3e?@*5b*5c*5d*5e_*60*7b*7c*7d*7e)",
+            ),
+            (
+                UrlContext::Fragment,
+                "~(k&=/~'*20*21*22*23!*25&'(*29*2a+,-./:;*3c=*3e?@*5b*5c*5d*5e_*60*7b*7c*7d*7e)",
+            ),
+            (
+                UrlContext::PathSegment,
+                "~(k&=*2f~'*20*21*22*23!*25&'(*29*2a+,-.*2f:;*3c=*3e*3f@*5b*5c*5d*5e_*60*7b*7c*7d*7e)",
+            ),
+        ];
+        for (context, expected) in cases {
+            let serializer = Serializer::new().url_context(context);
+            let serialized = serializer.serialize(&value);
+            assert_eq!(serialized, expected, "{:?}", context);
+            assert_eq!(deserialize(&serialized).unwrap(), value);
+            assert_eq!(deserialize_iterative(&serialized).unwrap(), value);
+            assert_eq!(serializer.serialized_len(&value), serialized.len());
+        }
+    }
+
+    #[test]
+    fn default_configs() {
+        static SERIALIZER: Serializer = Serializer::new();
+        static DESERIALIZER: Deserializer = Deserializer::new();
+
+        let value = serde_json::json!({"b": [1, "x y$"], "a": {"": null}});
+        let serialized = serialize(&value);
+        assert_eq!(SERIALIZER.serialize(&value), serialized);
+        assert_eq!(Serializer::default().serialize(&value), serialized);
+        assert_eq!(DESERIALIZER.deserialize(&serialized).unwrap(), value);
+        assert_eq!(
+            Deserializer::default().deserialize(&serialized).unwrap(),
+            value
+        );
+
+        let serializer = Serializer::new().sort_keys(true);
+        let copy = serializer.clone();
+        assert_eq!(copy.serialize(&value), serializer.serialize(&value));
+        let deserializer = Deserializer::new().max_depth(1);
+        let copy = deserializer.clone();
+        assert_eq!(
+            copy.deserialize("~(~(~1))").unwrap_err().kind(),
+            ErrorKind::DepthLimitExceeded
+        );
+    }
+
+    #[test]
+    fn separator_dialect_roundtrip() {
+        let value = serde_json::json!({
+            "": [[], {}, "", null],
+            "ab": [1.5, -2, true],
+            "c": {"d": "e f"},
+        });
+        let special = serde_json::json!({"a_b,;|@$": ["~_,;|@$'()*!"]});
+        for separator in ['_', ',', ';', '|', '@', '$', '~'] {
+            let serializer = Serializer::new().separator(separator);
+            let deserializer = Deserializer::new().separator(separator);
+            let serialized = serializer.serialize(&special);
+            assert_eq!(deserializer.deserialize(&serialized).unwrap(), special);
+            assert_eq!(serialized.matches(separator).count(), 3, "{}", serialized);
+
+            let serialized = serializer.serialize(&value);
+            assert_eq!(
+                serialized,
+                serialize(&value).replace('~', &String::from(separator)),
+                "{}",
+                separator
+            );
+            assert_eq!(deserializer.deserialize(&serialized).unwrap(), value);
+            assert_eq!(deserializer.validate(&serialized), Ok(()));
+            assert_eq!(
+                deserializer.deserialize_iterative(&serialized).unwrap(),
+                value
+            );
+            assert_eq!(serializer.serialized_len(&value), serialized.len());
+            let compact = serializer.serialize_compact(&value);
+            assert_eq!(deserializer.deserialize_compact(&compact).unwrap(), value);
+        }
+
+        // the separator is escaped in strings, also when it is otherwise left as-is
+        let underscore = Serializer::new().separator('_').safe_chars("_@");
+        assert_eq!(
+            underscore.serialize(&serde_json::json!({"a_b": "c_@"})),
+            "_(a*5fb_'c*5f@)"
+        );
+
+        let deserializer = Deserializer::new().separator('_');
+        for (input, kind, position) in [
+            ("~(a~1)", ErrorKind::UnexpectedChar('~'), 0),
+            ("_'a~b", ErrorKind::UnexpectedChar('~'), 3),
+            ("_(a__1)", ErrorKind::UnexpectedChar('_'), 4),
+            ("_(a_1", ErrorKind::UnexpectedEof, 5),
+        ] {
+            let err = deserializer.deserialize(input).unwrap_err();
+            assert_eq!((err.kind(), err.position()), (kind, position), "{}", input);
+        }
+        let percent = deserializer.clone().percent_decode(true);
+        assert_eq!(
+            percent.deserialize("_%28a_%27b%29").unwrap(),
+            serde_json::json!({"a": "b"})
+        );
+        assert_eq!(
+            deserializer.deserialize_lenient("?q=_(a_(_1))").unwrap(),
+            serde_json::json!({"a": [1]})
+        );
+        assert_eq!(
+            deserializer.deserialize_lenient("_'a=b").unwrap(),
+            serde_json::json!("a=b")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot be used as a jsurl separator")]
+    fn separator_rejects_number_chars() {
+        let _ = Serializer::new().separator('-');
+    }
+
+    #[cfg(feature = "ordered")]
+    #[test]
+    fn deserialize_ordered_keeps_input_order() {
+        fn keys(value: &OrderedValue) -> Vec<&str> {
+            match value {
+                OrderedValue::Object(o) => o.keys().map(String::as_str).collect(),
+                _ => panic!("not an object: {}", value),
+            }
+        }
+
+        let input = "~(z~1~m~(~'x~(c~true~b~null~a~()))~a~(~)~~'empty)";
+        let value = deserialize_ordered(input).unwrap();
+        assert_eq!(keys(&value), ["z", "m", "a", ""]);
+        let OrderedValue::Object(o) = &value else {
+            unreachable!()
+        };
+        let OrderedValue::Array(m) = &o["m"] else {
+            panic!("not an array")
+        };
+        assert_eq!(keys(&m[1]), ["c", "b", "a"]);
+        // written back in the same order
+        assert_eq!(value.to_string(), input);
+        assert_eq!(serde_json::Value::from(value), deserialize(input).unwrap());
+
+        // a repeated key keeps its first position and its last value
+        let value = deserialize_ordered("~(b~1~a~2~b~3)").unwrap();
+        assert_eq!(keys(&value), ["b", "a"]);
+        assert_eq!(value.to_string(), "~(b~3~a~2)");
+        let strict = Deserializer::new().reject_duplicate_keys(true);
+        let err = strict.deserialize_ordered("~(b~1~a~2~b~3)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+
+        for input in ["~null", "~-1.5", "~'a*20b!", "~(~1~(~))", "~()"] {
+            assert_eq!(deserialize_ordered(input).unwrap().to_string(), input);
+        }
+        assert_eq!(
+            deserialize_ordered("~(a").unwrap_err(),
+            deserialize("~(a").unwrap_err()
+        );
+    }
+
+    /// Renders values in a terse notation, to check what a [`Builder`] is handed.
+    struct Render;
+
+    impl Builder for Render {
+        type Value = String;
+        type Array = String;
+        type Object = String;
+
+        fn build_null(&mut self) -> String {
+            String::from("n")
+        }
+
+        fn build_bool(&mut self, b: bool) -> String {
+            String::from(if b { "t" } else { "f" })
+        }
+
+        fn build_number(&mut self, token: &str) -> Option<String> {
+            // reject anything that does not fit in an `i64`
+            token.parse::<i64>().ok().map(|_| String::from(token))
+        }
+
+        fn build_string(&mut self, s: Cow<'_, str>) -> String {
+            let borrowed = matches!(s, Cow::Borrowed(_));
+            String::from(if borrowed { "'" } else { "\"" }) + &s
+        }
+
+        fn begin_array(&mut self) -> String {
+            String::from("[")
+        }
+
+        fn push(&mut self, array: &mut String, value: String) {
+            array.push_str(&value);
+            array.push(' ');
+        }
+
+        fn end_array(&mut self, array: String) -> String {
+            array + "]"
+        }
+
+        fn begin_object(&mut self) -> String {
+            String::from("{")
+        }
+
+        fn insert(&mut self, object: &mut String, key: Cow<'_, str>, value: String) {
+            object.push_str(&key);
+            object.push('=');
+            object.push_str(&value);
+            object.push(' ');
+        }
+
+        fn end_object(&mut self, object: String) -> String {
+            object + "}"
+        }
+    }
+
+    #[test]
+    fn deserialize_with_builder() {
+        for (input, expected) in [
+            ("~null", "n"),
+            ("~(~true~false~-12)", "[t f -12 ]"),
+            ("~(~)", "[]"),
+            ("~()", "{}"),
+            ("~(b~'x~a~'y*20z~~(~))", "{b='x a=\"y z =[] }"),
+            ("~(~~(a~()))", "{={a={} } }"),
+        ] {
+            assert_eq!(deserialize_with(input, &mut Render).unwrap(), expected);
+        }
+
+        // the input is checked the same way as by `deserialize`
+        for input in [
+            "~(~1", "~(~1)~", "~01", "~nul", "~'a*zz", "~(a~1~b)", "~1.5",
+        ] {
+            let err = deserialize_with(input, &mut Render).unwrap_err();
+            match input {
+                // accepted by the default builder, rejected by this one
+                "~1.5" => assert_eq!(err, DeserializeError::new(ErrorKind::InvalidNumber, 1)),
+                _ => assert_eq!(err, deserialize(input).unwrap_err(), "{}", input),
+            }
+        }
+        let deserializer = Deserializer::new().reject_duplicate_keys(true).max_depth(2);
+        let err = deserializer
+            .deserialize_with("~(a~1~b~2~a~3)", &mut Render)
+            .unwrap_err();
+        assert_eq!(err, DeserializeError::new(ErrorKind::DuplicateKey, 10));
+        let err = deserializer
+            .deserialize_with("~(~(~(~1)))", &mut Render)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DepthLimitExceeded);
+
+        let input = "~(a~(~1~2.5~'x~null)~b~(c~true))";
+        assert_eq!(
+            deserialize_with(input, &mut ValueBuilder).unwrap(),
+            deserialize(input).unwrap()
+        );
+    }
+
+    #[test]
+    fn special_keys_roundtrip() {
+        let cases = [
+            (serde_json::json!({"a b": 1}), "~(a*20b~1)"),
+            (serde_json::json!({"$": 1, "!": 2}), "~(*21~2~!~1)"),
+            (
+                serde_json::json!({"caf\u{e9}": 1, "\u{1f600}": 2}),
+                "~(caf*e9~1~**d83d**de00~2)",
+            ),
+            (serde_json::json!({"(": 1, "'": 2}), "~(*27~2~*28~1)"),
+            (serde_json::json!({"": 1}), "~(~~1)"),
+            (serde_json::json!({"": []}), "~(~~(~))"),
+            (serde_json::json!({"": {"": null}}), "~(~~(~~null))"),
+            (serde_json::json!([{"": 1}]), "~(~(~~1))"),
+            (serde_json::json!({"": 1, "a": 2}), "~(~~1~a~2)"),
+        ];
+        // sorted, so that the keys are written in the same order without `preserve_order`
+        let serializer = Serializer::new().sort_keys(true);
+        for (value, expected) in cases {
+            let serialized = serializer.serialize(&value);
+            assert_eq!(serialized, expected);
+            assert_eq!(deserialize(&serialized).unwrap(), value, "{}", serialized);
+            assert_eq!(deserialize_iterative(&serialized).unwrap(), value);
+            assert!(validate(&serialized).is_ok());
+            let mut events = events(&serialized);
+            let first = events.next().unwrap().unwrap();
+            assert_eq!(value_from_events(first, &mut events).unwrap(), value);
+            assert!(events.next().is_none());
+            let mut parser = PushParser::new();
+            parser.feed(&serialized).unwrap();
+            assert_eq!(parser.finish().unwrap(), value);
+        }
+
+        // an empty key after the first one needs nothing extra
+        let entries = deserialize_entries("~(a~1~~2)").unwrap();
+        assert_eq!(entries[0], (String::from("a"), serde_json::json!(1)));
+        assert_eq!(entries[1], (String::new(), serde_json::json!(2)));
+
+        // the form without the extra `~` is still an array
+        assert_eq!(deserialize("~(~1)").unwrap(), serde_json::json!([1]));
+        let reference = Serializer::new().mark_empty_first_key(false);
+        assert_eq!(reference.serialize(&serde_json::json!({"": 1})), "~(~1)");
+        assert_eq!(
+            reference.serialize(&serde_json::json!([{"": []}])),
+            "~(~(~(~)))"
+        );
+    }
+
+    #[test]
+    fn url_query_value_roundtrip() {
+        let value = serde_json::json!({"a b": ["it's", "100%", {"": null}], "c": 1.5});
+        let encoded = to_url_query_value(&value);
+        assert!(!encoded.contains('\''));
+        assert_eq!(from_url_query_value(&encoded).unwrap(), value);
+        // after a URL library has decoded the query
+        let decoded = encoded.replace("%27", "'");
+        assert_eq!(decoded, serialize(&value));
+        assert_eq!(from_url_query_value(&decoded).unwrap(), value);
+        // with the structural characters percent-encoded as well
+        let escaped = encoded
+            .replace('~', "%7E")
+            .replace('(', "%28")
+            .replace(')', "%29");
+        assert_eq!(from_url_query_value(&escaped).unwrap(), value);
+    }
+
+    #[test]
+    fn url_safe_roundtrip() {
+        let value = serde_json::json!({
+            "a b": ["it's", "100%", {"": null}, "$!*()~'"],
+            "c": [1.5, -2, true, false, []],
+            "\u{e9}\u{1f600}": "\u{7f}&=#+?/",
+        });
+        let encoded = serialize_url_safe(&value);
+        assert!(encoded
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'%')));
+        assert_eq!(deserialize_url_safe(&encoded).unwrap(), value);
+        assert_eq!(deserialize_url_safe(&serialize(&value)).unwrap(), value);
+        assert_eq!(
+            serialize_url_safe(&serde_json::json!("a-b_c.d")),
+            "%7E%27a-b_c.d"
+        );
+
+        // a URL library leaves it alone, and its query pairs decode it fully
+        let mut url = url::Url::parse("https://example.com/").unwrap();
+        url.set_query(Some(&(String::from("q=") + &encoded)));
+        assert_eq!(url.query(), Some(&*(String::from("q=") + &encoded)));
+        let (_, decoded) = url.query_pairs().next().unwrap();
+        assert_eq!(decoded, serialize(&value));
+        assert_eq!(deserialize_url_safe(&decoded).unwrap(), value);
+    }
+
+    #[test]
+    fn compact_roundtrip() {
+        let values = [
+            serde_json::json!("hello world"),
+            serde_json::json!(""),
+            serde_json::json!(-1.5),
+            serde_json::json!(null),
+            serde_json::json!([]),
+            serde_json::json!({}),
+            serde_json::json!({"a": [1, {"b": "c"}], "": true}),
+        ];
+        for value in values {
+            let standard = serialize(&value);
+            let compact = serialize_compact(&value);
+            assert_eq!(compact, standard[1..]);
+            assert_eq!(deserialize_compact(&compact).unwrap(), value);
+        }
+
+        // the standard form is not compact input, and vice versa
+        assert!(deserialize_compact("~'hello").is_err());
+        assert!(deserialize("'hello").is_err());
+
+        let err = deserialize_compact("(a~1~b)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedChar(')'));
+        assert_eq!(err.position(), 6);
+        assert_eq!(deserialize_compact("").unwrap_err().position(), 0);
+    }
+
+    #[test]
+    fn serialize_skip_nulls() {
+        let value = serde_json::json!({
+            "a": null,
+            "b": {"c": null, "d": {"e": null}, "f": 1},
+            "g": [null, {"h": null}],
+            "i": null,
+        });
+        let serializer = Serializer::new().skip_nulls(true);
+        let serialized = serializer.serialize(&value);
+        assert_eq!(serialized, "~(b~(d~()~f~1)~g~(~null~()))");
+        assert_eq!(serializer.serialized_len(&value), serialized.len());
+        assert_eq!(
+            deserialize(&serialized).unwrap(),
+            serde_json::json!({"b": {"d": {}, "f": 1}, "g": [null, {}]})
+        );
+
+        assert_eq!(serializer.serialize(&serde_json::json!({"a": null})), "~()");
+        assert_eq!(serializer.serialize(&serde_json::Value::Null), "~null");
+        assert_eq!(
+            serializer.sort_keys(true).serialize(&value),
+            "~(b~(d~()~f~1)~g~(~null~()))"
+        );
+    }
+
+    #[test]
+    fn deserialize_trimmed() {
+        let trimming = Deserializer::new().trim(true);
+        for input in [
+            "\u{feff}~(a~1)\n",
+            "~(a~1)",
+            "  ~(a~1)\t\r\n",
+            "\u{feff} ~(a~1) ",
+        ] {
+            assert_eq!(
+                trimming.deserialize(input).unwrap(),
+                serde_json::json!({"a": 1})
+            );
+            assert!(trimming.validate(input).is_ok());
+        }
+        assert_eq!(
+            deserialize("\u{feff}~(a~1)\n").unwrap_err(),
+            DeserializeError::new(ErrorKind::UnexpectedChar('\u{feff}'), 0)
+        );
+        assert_eq!(
+            deserialize("~(a~1)\n").unwrap_err().kind(),
+            ErrorKind::TrailingCharacters
+        );
+
+        // only the outside is trimmed, and only one BOM, before any whitespace
+        assert_eq!(
+            trimming.deserialize(" ~'a b ").unwrap(),
+            serde_json::json!("a b")
+        );
+        for (input, kind, position) in [
+            (
+                "\u{feff}\u{feff}~1",
+                ErrorKind::UnexpectedChar('\u{feff}'),
+                1,
+            ),
+            (" \u{feff}~1", ErrorKind::UnexpectedChar('\u{feff}'), 1),
+            ("\u{feff}\n ~(a~)", ErrorKind::UnexpectedChar(')'), 7),
+            ("\u{a0}~1", ErrorKind::UnexpectedChar('\u{a0}'), 0),
+        ] {
+            let err = trimming.deserialize(input).unwrap_err();
+            assert_eq!(
+                (err.kind(), err.position()),
+                (kind, position),
+                "{:?}",
+                input
+            );
+        }
+        let trimming = trimming.percent_decode(true);
+        assert_eq!(
+            trimming.deserialize(" %7E1\n").unwrap(),
+            serde_json::json!(1)
+        );
+    }
+
+    #[test]
+    fn merge_objects() {
+        let merged =
+            |base, patch, arrays| deserialize(merge_with(base, patch, arrays).unwrap()).unwrap();
+        let base = "~(a~1~b~(c~(~1~2)~d~'x)~e~(f~null))";
+        for (patch, replaced, concatenated) in [
+            (
+                "~(b~(c~(~3)~g~true))",
+                serde_json::json!({"a": 1, "b": {"c": [3], "d": "x", "g": true}, "e": {"f": null}}),
+                serde_json::json!({"a": 1, "b": {"c": [1, 2, 3], "d": "x", "g": true}, "e": {"f": null}}),
+            ),
+            // an object and anything else replace each other
+            (
+                "~(a~(x~1)~b~(~1)~e~null)",
+                serde_json::json!({"a": {"x": 1}, "b": [1], "e": null}),
+                serde_json::json!({"a": {"x": 1}, "b": [1], "e": null}),
+            ),
+            (
+                "~()",
+                deserialize(base).unwrap(),
+                deserialize(base).unwrap(),
+            ),
+        ] {
+            assert_eq!(
+                merged(base, patch, ArrayMerge::Replace),
+                replaced,
+                "{}",
+                patch
+            );
+            assert_eq!(
+                merged(base, patch, ArrayMerge::Concat),
+                concatenated,
+                "{}",
+                patch
+            );
+            assert_eq!(
+                merge(base, patch).unwrap(),
+                merge_with(base, patch, ArrayMerge::default()).unwrap()
+            );
+        }
+        // top-level values that are not objects
+        assert_eq!(merge("~(~1)", "~(~2)").unwrap(), "~(~2)");
+        assert_eq!(
+            merge_with("~(~1)", "~(~2)", ArrayMerge::Concat).unwrap(),
+            "~(~1~2)"
+        );
+        assert_eq!(merge("~(a~1)", "~'x").unwrap(), "~'x");
+        assert_eq!(merge("~null", "~(a~1)").unwrap(), "~(a~1)");
+
+        // errors in the base come first
+        assert_eq!(
+            merge("~(a", "~(b").unwrap_err(),
+            deserialize("~(a").unwrap_err()
+        );
+        assert_eq!(
+            merge("~()", "~(b~").unwrap_err(),
+            deserialize("~(b~").unwrap_err()
+        );
+    }
+
+    #[test]
+    fn serialize_null_as() {
+        let value = serde_json::json!({"a": null, "b": [null, 1, null], "c": {"": null}});
+        assert_eq!(
+            Serializer::new().null_as(NullRepr::Null).serialize(&value),
+            serialize(&value)
+        );
+        let serializer = Serializer::new().null_as(NullRepr::EmptyString);
+        let serialized = serializer.serialize(&value);
+        assert_eq!(serialized, "~(a~'~b~(~'~1~')~c~(~~'))");
+        assert_eq!(serializer.serialized_len(&value), serialized.len());
+        assert_eq!(
+            deserialize(&serialized).unwrap(),
+            serde_json::json!({"a": "", "b": ["", 1, ""], "c": {"": ""}})
+        );
+        assert_eq!(serializer.serialize(&serde_json::Value::Null), "~'");
+        // skipped entries are not written at all
+        let serializer = serializer.skip_nulls(true);
+        assert_eq!(serializer.serialize(&value), "~(b~(~'~1~')~c~())");
+
+        // the default is unchanged, and `~null` is still read
+        assert_eq!(NullRepr::default(), NullRepr::Null);
+        assert_eq!(serialize(&serde_json::Value::Null), "~null");
+        assert_eq!(deserialize("~null").unwrap(), serde_json::Value::Null);
+    }
+
+    #[test]
+    fn serialize_dollar_as_hex() {
+        let value = serde_json::json!({"$": ["a$b", "$$"]});
+        let serializer = Serializer::new().escape_dollar_as_hex(true);
+        let serialized = serializer.serialize(&value);
+        assert_eq!(serialized, "~(*24~(~'a*24b~'*24*24))");
+        assert!(!serialized.contains('!'));
+        assert_eq!(deserialize(&serialized).unwrap(), value);
+        assert_eq!(serializer.serialized_len(&value), serialized.len());
+
+        // the option wins over readable mode and safe_chars
+        let serializer = serializer.readable(true).safe_chars("$");
+        assert_eq!(serializer.serialize(&value), serialized);
+        assert_eq!(deserialize("~'!*24").unwrap(), serde_json::json!("$$"));
+    }
+
+    #[test]
+    fn try_serialize_values() {
+        let obj = serde_json::json!({"a": [1, 2.5, u64::MAX, -3, "x"]});
+        assert_eq!(try_serialize(&obj).unwrap(), serialize(&obj));
+        assert_eq!(
+            Serializer::new()
+                .sort_keys(true)
+                .try_serialize(&obj)
+                .unwrap(),
+            "~(a~(~1~2.5~18446744073709551615~-3~'x))"
+        );
+        assert_eq!(
+            SerializeError::UnsupportedNumber.to_string(),
+            "unsupported number type"
+        );
+    }
+
+    #[test]
+    fn serialized_len_is_exact() {
+        let values = [
+            serde_json::json!(null),
+            serde_json::json!(-12.5e-9),
+            serde_json::json!(u64::MAX),
+            serde_json::json!("$ \u{e9}\u{1f600}~"),
+            serde_json::json!([[], {}, [true, false]]),
+            serde_json::json!({"name": "John Doe", "age": 42, "children": ["Mary", "Bill"]}),
+        ];
+        for value in values.iter() {
+            assert_eq!(serialized_len(value), serialize(value).len(), "{}", value);
+            let readable = Serializer::new().readable(true);
+            assert_eq!(
+                readable.serialized_len(value),
+                readable.serialize(value).len(),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn serialize_reusing_buffer() {
+        let mut buf = String::from("leftover from a previous value");
+        serialize_reuse(&serde_json::json!({"a": [1, "b"]}), &mut buf);
+        assert_eq!(buf, "~(a~(~1~'b))");
+        let capacity = buf.capacity();
+        serialize_reuse(&serde_json::json!(true), &mut buf);
+        assert_eq!(buf, "~true");
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn serialize_to_existing_writer() {
+        let obj = serde_json::json!({"a b": ["c", 1, null]});
+        let mut output = String::from("?q=");
+        serialize_to_writer(&obj, &mut output).unwrap();
+        assert_eq!(output, "?q=~(a*20b~(~'c~1~null))");
+
+        struct Failing;
+        impl core::fmt::Write for Failing {
+            fn write_str(&mut self, _: &str) -> core::fmt::Result {
+                Err(core::fmt::Error)
+            }
+        }
+        assert!(serialize_to_writer(&obj, &mut Failing).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn serialize_to_io_writer() {
+        let obj = serde_json::json!({"caf\u{e9}": ["\u{1f600}", 1.5, null]});
+        let mut output = b"?q=".to_vec();
+        serialize_to_io(&obj, &mut output).unwrap();
+        assert_eq!(output, b"?q=~(caf*e9~(~'**d83d**de00~1.5~null))");
+
+        let readable = Serializer::new().readable(true);
+        let mut output = Vec::new();
+        readable.serialize_to_io(&obj, &mut output).unwrap();
+        assert_eq!(output, readable.serialize(&obj).into_bytes());
+
+        // the writer's own error is passed through
+        let mut full = [0u8; 4];
+        let err = serialize_to_io(&obj, &mut &mut full[..]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+        assert_eq!(&full, b"~(ca");
+    }
+
+    // relies on serde_json's preserve_order feature
+    #[cfg(feature = "std")]
+    #[test]
+    fn serialize_example() {
+        assert_serialize_eq!(
+            r#"{"name":"John Doe","age":42,"children":["Mary","Bill"]}"#,
+            "~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))",
+        );
+    }
+
+    #[test]
+    fn deserialize_basic_values() {
+        assert_deserialize_eq!("null", "~null");
+        assert_deserialize_eq!("true", "~true");
+        assert_deserialize_eq!("false", "~false");
+        assert_deserialize_eq!("0", "~0");
+        assert_deserialize_eq!("1", "~1");
+        assert_deserialize_eq!("-1.5", "~-1.5");
+    }
+
+    #[test]
+    fn deserialize_strings() {
+        let s1 = serde_json::Value::String("hello world\u{203c}".to_string());
+        assert_eq!(deserialize("~'hello*20world**203c").unwrap(), s1);
+
+        let s2 = serde_json::Value::String(" !\"#$%&'()*+,-./09:;<=>?@AZ[\\]^_`az{|}~".to_string());
+        assert_eq!(deserialize("~'*20*21*22*23!*25*26*27*28*29*2a*2b*2c-.*2f09*3a*3b*3c*3d*3e*3f*40AZ*5b*5c*5d*5e_*60az*7b*7c*7d*7e").unwrap(), s2);
+    }
+
+    #[test]
+    fn deserialize_arrays() {
+        assert_deserialize_eq!("[]", "~(~)");
+        assert_deserialize_eq!("[1,2,3]", "~(~1~2~3)");
+        assert_deserialize_eq!(
+            r#"[null, false, 0, "hello world\u203c"]"#,
+            "~(~null~false~0~'hello*20world**203c)"
+        );
+    }
+
+    #[test]
+    fn deserialize_objects() {
+        assert_deserialize_eq!("{}", "~()");
+        assert_deserialize_eq!(
+            r#"{"c":null,"d":false,"e":0,"f":"hello world\u203c"}"#,
+            "~(c~null~d~false~e~0~f~'hello*20world**203c)"
+        );
+        assert_deserialize_eq!(
+            r#"{"a": [[1, 2],[], {"a1": 3}],"b": [],"c": {"d": "hello","e": {},"f": []}}"#,
+            "~(a~(~(~1~2)~(~)~(a1~3))~b~(~)~c~(d~'hello~e~()~f~(~)))",
+        );
+    }
+
+    #[test]
+    fn deserialize_example() {
+        assert_deserialize_eq!(
+            r#"{"name":"John Doe","age":42,"children":["Mary","Bill"]}"#,
+            "~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))",
+        );
+    }
+
+    // arbitrary precision numbers are written verbatim instead
+    #[cfg(not(feature = "arbitrary_precision"))]
+    #[test]
+    fn serialize_floats_like_javascript() {
+        let f = |n: f64| serialize(&serde_json::Value::from(n));
+        assert_eq!(f(0.1), "~0.1");
+        assert_eq!(f(-1.5), "~-1.5");
+        assert_eq!(f(123.456), "~123.456");
+        assert_eq!(f(42.0), "~42");
+        assert_eq!(f(1e20), "~100000000000000000000");
+        assert_eq!(f(1e21), "~1e+21");
+        assert_eq!(f(1.5e300), "~1.5e+300");
+        assert_eq!(f(-1.2345e25), "~-1.2345e+25");
+        assert_eq!(f(0.000001), "~0.000001");
+        assert_eq!(f(1e-7), "~1e-7");
+        assert_eq!(f(1.5e-10), "~1.5e-10");
+        assert_eq!(f(f64::MAX), "~1.7976931348623157e+308");
+        assert_eq!(f(f64::MIN_POSITIVE), "~2.2250738585072014e-308");
+        assert_eq!(f(5e-324), "~5e-324");
+
+        // the last one is read back one digit off by serde_json's own float parser
+        for n in [
+            0.1,
+            1e21,
+            1e-7,
+            1.5e-10,
+            123.456,
+            f64::MAX,
+            5e-324,
+            1.1868703194890907e-209,
+        ] {
+            let value = serde_json::Value::from(n);
+            assert_eq!(deserialize(serialize(&value)).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn deserialize_exponents() {
+        for (input, expected) in [
+            ("~1e21", 1e21),
+            ("~1E21", 1e21),
+            ("~1e+21", 1e21),
+            ("~1.5e-10", 1.5e-10),
+            ("~-2.5E+3", -2500.0),
+            ("~0e0", 0.0),
+            ("~1.7976931348623157e+308", f64::MAX),
+        ] {
+            // compared as `f64`, since with `arbitrary_precision` the number keeps its text
+            let value = deserialize(input).unwrap();
+            assert_eq!(value.as_f64(), Some(expected), "{}", input);
+            assert_eq!(deserialize_iterative(input).unwrap(), value);
+            assert!(validate(input).is_ok());
+            // whatever form the number is written back in is accepted too, although an
+            // integral float such as `-2500.0` comes back as an integer
+            let reserialized = deserialize(serialize(&value)).unwrap();
+            assert_eq!(reserialized.as_f64(), Some(expected), "{}", input);
+        }
+        for input in ["~1e", "~1e+", "~1E-", "~1.e5", "~1e2.5", "~1e5e5", "~1e++5"] {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidNumber, "{}", input);
+        }
+        // a token must start with a digit or `-` to be a number at all
+        assert_eq!(
+            deserialize("~e5").unwrap_err().kind(),
+            ErrorKind::InvalidLiteral
+        );
+    }
+
+    #[cfg(not(feature = "arbitrary_precision"))]
+    #[test]
+    fn integral_floats_roundtrip() {
+        // by default an integral float comes back as an integer
+        let value = serde_json::Value::from(3.0);
+        assert_eq!(serialize(&value), "~3");
+        let deserialized = deserialize("~3").unwrap();
+        assert!(deserialized.is_u64());
+        assert_eq!(deserialized, serde_json::json!(3));
+        assert!(deserialize("~3.0").unwrap().is_f64());
+
+        let preserving = Serializer::new().preserve_floats(true);
+        let f = |n: f64| preserving.serialize(&serde_json::Value::from(n));
+        assert_eq!(f(3.0), "~3.0");
+        assert_eq!(f(-42.0), "~-42.0");
+        assert_eq!(f(0.0), "~0.0");
+        assert_eq!(f(1e20), "~100000000000000000000.0");
+        assert_eq!(f(1e21), "~1e+21");
+        assert_eq!(f(2.5), "~2.5");
+        assert_eq!(f(1e-7), "~1e-7");
+        for n in [3.0, -42.0, 0.0, 1e20, 1e21, 2.5, 1e300] {
+            let value = serde_json::Value::from(n);
+            let deserialized = deserialize(preserving.serialize(&value)).unwrap();
+            assert!(deserialized.is_f64(), "{}", n);
+            assert_eq!(deserialized, value);
+        }
+        // integers are unaffected
+        assert_eq!(
+            preserving.serialize(&serde_json::json!([3, -1])),
+            "~(~3~-1)"
+        );
+    }
+
+    #[test]
+    fn serialize_integer_arrays() {
+        for n in [
+            0,
+            7,
+            -7,
+            10,
+            -10,
+            1234567890,
+            i64::MAX,
+            i64::MIN,
+            i64::MIN + 1,
+        ] {
+            let mut output = String::new();
+            write_integer_value(n, &mut output).unwrap();
+            assert_eq!(output, String::from("~") + &n.to_string());
+        }
+        assert_eq!(
+            serialize(&serde_json::json!([0, -1, i64::MIN, i64::MAX])),
+            "~(~0~-1~-9223372036854775808~9223372036854775807)"
+        );
+        // mixed arrays take the general path
+        assert_eq!(
+            serialize(&serde_json::json!([1, u64::MAX, 2.5, "x"])),
+            "~(~1~18446744073709551615~2.5~'x)"
+        );
+        let ints: Vec<i64> = (-1000..1000).map(|i| i * 9973).collect();
+        let value = serde_json::json!(ints);
+        assert_eq!(deserialize(serialize(&value)).unwrap(), value);
+    }
+
+    #[cfg(not(feature = "arbitrary_precision"))]
+    #[test]
+    fn negative_zero_is_written_as_zero() {
+        let zero = serde_json::Value::from(0.0);
+        let negative_zero = serde_json::Value::from(-0.0);
+        assert_eq!(serialize(&negative_zero), "~0");
+        assert_eq!(serialize(&negative_zero), serialize(&zero));
+        assert_eq!(
+            serialize_canonical(&negative_zero),
+            serialize_canonical(&zero)
+        );
+        let preserving = Serializer::new().preserve_floats(true);
+        assert_eq!(preserving.serialize(&negative_zero), "~0.0");
+
+        // "-0" is still accepted on input, and normalized on the way back out
+        let value = deserialize("~-0").unwrap();
+        assert_eq!(value, zero);
+        assert_eq!(serialize(&value), "~0");
+    }
+
+    #[test]
+    fn serialize_non_finite_floats_as_null() {
+        for n in [f64::NAN, -f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let mut output = String::new();
+            write_float_value(n, &mut output).unwrap();
+            assert_eq!(output, "~null", "{}", n);
+        }
+        let mut output = String::new();
+        write_float_value(-1.5, &mut output).unwrap();
+        assert_eq!(output, "~-1.5");
+        // serde_json already turns them into `Value::Null` when building a `Value`
+        assert_eq!(serialize(&serde_json::json!([f64::NAN])), "~(~null)");
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn serialize_arbitrary_precision() {
+        let digits = "1234567890123456789012345678901234567890";
+        let value: serde_json::Value = serde_json::from_str(digits).unwrap();
+        assert_eq!(serialize(&value), format!("~{}", digits));
+        assert_eq!(deserialize(serialize(&value)).unwrap(), value);
+
+        let value: serde_json::Value = serde_json::from_str("-0.100000000000000000000001").unwrap();
+        assert_eq!(serialize(&value), "~-0.100000000000000000000001");
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn roundtrip_bignum_integers() {
+        for digits in [
+            "12345678901234567890123456789012345678901234567890",
+            "-98765432109876543210987654321098765432109876543210",
+        ] {
+            assert_eq!(digits.trim_start_matches('-').len(), 50);
+            let value: serde_json::Value =
+                serde_json::from_str(&format!("{{\"n\":[{}]}}", digits)).unwrap();
+            let serialized = serialize(&value);
+            assert_eq!(serialized, format!("~(n~(~{}))", digits));
+
+            let deserialized = deserialize(&serialized).unwrap();
+            assert_eq!(deserialized, value);
+            assert_eq!(deserialized["n"][0].to_string(), digits);
+            assert_eq!(
+                deserialize_iterative(&serialized).unwrap()["n"][0].to_string(),
+                digits
+            );
+        }
+
+        let digits = "1234567890".repeat(100);
+        let deserialized = deserialize(format!("~(~{}~{})", digits, digits)).unwrap();
+        assert_eq!(deserialized[1].to_string(), digits);
+    }
+
+    #[test]
+    fn roundtrip_large_integers() {
+        assert_serialize_eq!("18446744073709551615", "~18446744073709551615");
+        assert_serialize_eq!("-9223372036854775808", "~-9223372036854775808");
+
+        let max = serde_json::Value::from(u64::MAX);
+        assert_eq!(deserialize(serialize(&max)).unwrap(), max);
+    }
+
+    #[test]
+    fn serialize_value_struct() {
+        #[derive(serde::Serialize)]
+        struct Point {
+            x: i32,
+            y: i32,
+            label: Option<String>,
+        }
+
+        let p = Point {
+            x: 1,
+            y: -2,
+            label: None,
+        };
+        let serialized = serialize_value(&p).unwrap();
+        #[cfg(feature = "std")]
+        assert_eq!(serialized, "~(x~1~y~-2~label~null)");
+        assert_eq!(
+            deserialize(&serialized).unwrap(),
+            serde_json::json!({"x": 1, "y": -2, "label": null})
+        );
+        assert_eq!(serialize_value(&[1, 2, 3]).unwrap(), "~(~1~2~3)");
+        assert_eq!(serialize_value("a b").unwrap(), "~'a*20b");
+
+        let mut bad = alloc::collections::BTreeMap::new();
+        bad.insert((1, 2), "tuple keys are not valid JSON");
+        assert!(matches!(serialize_value(&bad).unwrap_err(), Error::Json(_)));
+    }
+
+    #[test]
+    fn serialize_value_maps() {
+        let mut scores = alloc::collections::BTreeMap::new();
+        scores.insert(String::from("bob"), -3);
+        scores.insert(String::from("alice"), 12);
+        assert_eq!(serialize_value(&scores).unwrap(), "~(alice~12~bob~-3)");
+
+        // keys are converted to strings the same way JSON object keys are
+        let mut numbered = alloc::collections::BTreeMap::new();
+        numbered.insert(2, 'b');
+        numbered.insert(1, 'a');
+        assert_eq!(serialize_value(&numbered).unwrap(), "~(1~'a~2~'b)");
+        let mut flags = alloc::collections::BTreeMap::new();
+        flags.insert(true, ());
+        assert_eq!(serialize_value(&flags).unwrap(), "~(true~null)");
+
+        let mut bad = alloc::collections::BTreeMap::new();
+        bad.insert([1], 1);
+        let err = serialize_value(&bad).unwrap_err();
+        assert!(matches!(err, Error::Json(_)));
+        assert_eq!(err.to_string(), "key must be a string");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn serialize_value_hash_map() {
+        let mut tags = std::collections::HashMap::new();
+        tags.insert(
+            String::from("colors"),
+            vec![String::from("red"), String::from("dark blue")],
+        );
+        tags.insert(String::from("sizes"), vec![]);
+        let serialized = serialize_value(&tags).unwrap();
+        assert_eq!(
+            deserialize(&serialized).unwrap(),
+            serde_json::json!({"colors": ["red", "dark blue"], "sizes": []})
+        );
+        assert_eq!(
+            Serializer::new()
+                .sort_keys(true)
+                .serialize_value(&tags)
+                .unwrap(),
+            "~(colors~(~'red~'dark*20blue)~sizes~(~))"
+        );
+    }
+
+    #[test]
+    fn serde_value_string_newtypes() {
+        use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+        use uuid::Uuid;
+
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Session {
+            id: Uuid,
+            started: DateTime<Utc>,
+            expires: DateTime<FixedOffset>,
+        }
+
+        let session = Session {
+            id: Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8),
+            started: Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap(),
+            expires: FixedOffset::east_opt(2 * 3600)
+                .unwrap()
+                .with_ymd_and_hms(2024, 5, 1, 16, 30, 15)
+                .unwrap(),
+        };
+        // sorted, since the field order is only kept with `std`
+        let serialized = Serializer::new()
+            .sort_keys(true)
+            .serialize_value(&session)
+            .unwrap();
+        // both serialize as strings: `-` is left as it is, while `:` and `+` are escaped
+        assert_eq!(
+            serialized,
+            String::from("~(expires~'2024-05-01T16*3a30*3a15*2b02*3a00")
+                + "~id~'67e55044-10b1-426f-9247-bb680e5fe0c8"
+                + "~started~'2024-05-01T12*3a30*3a00Z)"
+        );
+        assert_eq!(deserialize_into::<Session>(&serialized).unwrap(), session);
+    }
+
+    #[test]
+    fn deserialize_into_struct() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Point {
+            x: i32,
+            y: i32,
+            label: Option<String>,
+        }
+
+        let p: Point = deserialize_into("~(x~1~y~-2~label~'origin)").unwrap();
+        assert_eq!(
+            p,
+            Point {
+                x: 1,
+                y: -2,
+                label: Some("origin".to_string())
+            }
+        );
+
+        let err = deserialize_into::<Point>("~(x~1~y~").unwrap_err();
+        assert!(matches!(err, Error::Deserialize(_)));
+
+        let err = deserialize_into::<Point>("~(x~'one~y~2)").unwrap_err();
+        assert!(matches!(err, Error::Json(_)));
+    }
+
+    #[test]
+    fn jsurl_wrapper() {
+        let mut value: JsUrl = "~(a~1~b~(~'x))".parse().unwrap();
+        assert_eq!(*value, serde_json::json!({"a": 1, "b": ["x"]}));
+        assert!(value.is_object());
+
+        value["a"] = serde_json::json!("one two");
+        assert_eq!(value.to_string(), "~(a~'one*20two~b~(~'x))");
+        assert_eq!(value.to_string().parse::<JsUrl>().unwrap(), value);
+
+        assert_eq!(
+            JsUrl::try_from("~null").unwrap().into_inner(),
+            serde_json::Value::Null
+        );
+        let err = "~(a".parse::<JsUrl>().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert!(JsUrl::try_from("nope").is_err());
+
+        let s: String = JsUrl::from(serde_json::json!({"a": [1]})).into();
+        assert_eq!(s, "~(a~(~1))");
+        assert_eq!(
+            JsUrl::try_from(s).unwrap(),
+            JsUrl(serde_json::json!({"a": [1]}))
+        );
+        assert!(JsUrl::try_from(String::from("~(")).is_err());
+
+        let mut line = String::from("state=");
+        write!(line, "{}&page={}", JsUrl(serde_json::json!([null, "a"])), 2).unwrap();
+        assert_eq!(line, "state=~(~null~'a)&page=2");
+    }
+
+    #[cfg(feature = "serde_impls")]
+    #[test]
+    fn jsurl_serde_impls() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Link {
+            href: String,
+            state: JsUrl,
+        }
+
+        let link = Link {
+            href: String::from("/search"),
+            state: JsUrl(serde_json::json!({"q": "rust url", "page": 2})),
+        };
+        let json = serde_json::to_string(&link).unwrap();
+        #[cfg(feature = "std")]
+        assert_eq!(
+            json,
+            r#"{"href":"/search","state":"~(q~'rust*20url~page~2)"}"#
+        );
+        assert_eq!(serde_json::from_str::<Link>(&json).unwrap(), link);
+
+        let err = serde_json::from_str::<Link>(r#"{"href":"/","state":"~(q~"}"#).unwrap_err();
+        assert!(
+            err.to_string().contains("unexpected end of input"),
+            "{}",
+            err
+        );
+        let err = serde_json::from_str::<Link>(r#"{"href":"/","state":1}"#).unwrap_err();
+        assert!(err.to_string().contains("a jsurl string"), "{}", err);
+    }
+
+    #[test]
+    fn canonical_jsurl_keys() {
+        let a = CanonicalJsUrl::new(&serde_json::json!({"x": [1, {"q": 1, "p": 2}], "a": null}));
+        let b = CanonicalJsUrl::from(&serde_json::json!({"a": null, "x": [1, {"p": 2, "q": 1}]}));
+        assert_eq!(a, b);
+        assert_eq!(a.as_str(), "~(a~null~x~(~1~(p~2~q~1)))");
+        assert_eq!(a.to_string(), a.as_str());
+
+        let mut map = alloc::collections::BTreeMap::new();
+        map.insert(a, 1);
+        map.insert(CanonicalJsUrl::new(&serde_json::json!([])), 2);
+        assert_eq!(map.get(&b), Some(&1));
+        assert_eq!(map.get("~(~)"), Some(&2));
+        assert_eq!(
+            map.into_keys()
+                .map(CanonicalJsUrl::into_string)
+                .collect::<Vec<_>>(),
+            ["~(a~null~x~(~1~(p~2~q~1)))", "~(~)"]
+        );
+    }
+
+    /// Round trips of generated values, which cover combinations that the tables above
+    /// would miss.
+    #[cfg(feature = "std")]
+    mod generated {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// Short strings biased towards the chars that need care: structural and URL chars,
+        /// control chars, and chars outside the BMP, which are escaped as surrogate pairs.
+        fn string() -> impl Strategy<Value = String> {
+            let special = prop::sample::select(
+                &[
+                    '~', '(', ')', '\'', '*', '!', '$', ' ', '+', '%', '&', '=', '?', '#',
+                ][..],
+            );
+            let control = (0u8..0x20).prop_map(char::from);
+            let astral = (0x10000u32..0x110000).prop_filter_map("not a char", char::from_u32);
+            let c = prop_oneof![4 => any::<char>(), 3 => special, 1 => control, 1 => astral];
+            prop::collection::vec(c, 0..8).prop_map(String::from_iter)
+        }
+
+        fn value(float: BoxedStrategy<f64>) -> impl Strategy<Value = serde_json::Value> {
+            let leaf = prop_oneof![
+                Just(serde_json::Value::Null),
+                any::<bool>().prop_map(serde_json::Value::Bool),
+                any::<i64>().prop_map(serde_json::Value::from),
+                any::<u64>().prop_map(serde_json::Value::from),
+                // non-finite floats become `null`
+                float.prop_map(serde_json::Value::from),
+                string().prop_map(serde_json::Value::String),
+            ];
+            leaf.prop_recursive(4, 64, 6, |inner| {
+                prop_oneof![
+                    prop::collection::vec(inner.clone(), 0..6).prop_map(serde_json::Value::Array),
+                    prop::collection::vec((string(), inner), 0..6).prop_map(|entries| {
+                        serde_json::Value::Object(entries.into_iter().collect())
+                    }),
+                ]
+            })
+        }
+
+        fn assert_roundtrip(serializer: &Serializer, value: &serde_json::Value) {
+            let serialized = serializer.serialize(value);
+            assert_eq!(&deserialize(&serialized).unwrap(), value, "{}", serialized);
+            assert_eq!(&deserialize_iterative(&serialized).unwrap(), value);
+            let mut parser = PushParser::new();
+            parser.feed(&serialized).unwrap();
+            assert_eq!(&parser.finish().unwrap(), value);
+        }
+
+        proptest! {
+            #[test]
+            fn roundtrip(value in value(
+                // integral floats come back as integers, see `preserve_floats`
+                any::<f64>().prop_filter("integral", |n| n.fract() != 0.0).boxed()
+            )) {
+                assert_roundtrip(&Serializer::new(), &value);
+                assert!(roundtrip_check(&value));
+            }
+
+            #[test]
+            fn roundtrip_preserving_floats(value in value(any::<f64>().boxed())) {
+                assert_roundtrip(&Serializer::new().preserve_floats(true), &value);
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_check_values() {
+        assert!(roundtrip_check(&serde_json::json!({
+            "a": [1, -2, 3.5, "four", null, true, {}, []],
+            "b c": {"\u{1f600}": "~)*!$'"},
+        })));
+        // integral floats come back as integers
+        #[cfg(not(feature = "arbitrary_precision"))]
+        assert!(!roundtrip_check(&serde_json::json!(3.0)));
+    }
+
+    #[test]
+    fn space_as_plus_roundtrip() {
+        let serializer = Serializer::new().space_as_plus(true);
+        let deserializer = Deserializer::new().plus_as_space(true);
+        for (value, expected) in [
+            (serde_json::json!("a b"), "~'a+b"),
+            (serde_json::json!("a+b"), "~'a*2bb"),
+            (serde_json::json!(" + "), "~'+*2b+"),
+            (
+                serde_json::json!({"c++ lang": ["1 + 2", "+"]}),
+                "~(c*2b*2b+lang~(~'1+*2b+2~'*2b))",
+            ),
+            (serde_json::json!("caf\u{e9} +$"), "~'caf*e9+*2b!"),
+        ] {
+            let serialized = serializer.serialize(&value);
+            assert_eq!(serialized, expected);
+            assert_eq!(deserializer.deserialize(&serialized).unwrap(), value);
+            let mut parser = deserializer.push_parser();
+            parser.feed(&serialized).unwrap();
+            assert_eq!(parser.finish().unwrap(), value);
+        }
+
+        // a literal `+` stays escaped even where it would otherwise be left as-is
+        for serializer in [
+            serializer.clone().url_context(UrlContext::Fragment),
+            serializer.clone().safe_chars("+"),
+            serializer.clone().readable(true),
+        ] {
+            assert_eq!(serializer.serialize(&serde_json::json!("a+ b")), "~'a*2b+b");
+        }
+
+        // without the options, `+` is an ordinary char
+        assert_eq!(deserialize("~'a+b").unwrap(), serde_json::json!("a+b"));
+        assert_eq!(
+            deserializer.deserialize("~'a*2b+b").unwrap(),
+            serde_json::json!("a+ b")
+        );
+        let deserializer = deserializer.percent_decode(true);
+        assert_eq!(
+            deserializer.deserialize("~'a%2Bb").unwrap(),
+            serde_json::json!("a b")
+        );
+    }
+
+    #[test]
+    fn structural_characters_roundtrip() {
+        for (s, expected) in [
+            ("a~b", "~'a*7eb"),
+            ("a(b", "~'a*28b"),
+            ("a)b", "~'a*29b"),
+            ("a'b", "~'a*27b"),
+            ("a*b", "~'a*2ab"),
+            ("a!b", "~'a*21b"),
+            ("a$b", "~'a!b"),
+            ("~()'*!$", "~'*7e*28*29*27*2a*21!"),
+        ] {
+            let value = serde_json::json!(s);
+            assert_eq!(serialize(&value), expected);
+            assert_eq!(deserialize(expected).unwrap(), value);
+
+            // the same characters in keys and nested positions
+            let value = serde_json::json!({ s: [s, { s: s }] });
+            let serialized = serialize(&value);
+            assert_eq!(deserialize(&serialized).unwrap(), value, "{}", serialized);
+            assert_eq!(deserialize_iterative(&serialized).unwrap(), value);
+            for context in [
+                UrlContext::Query,
+                UrlContext::Fragment,
+                UrlContext::PathSegment,
+            ] {
+                let serializer = Serializer::new().url_context(context);
+                let serialized = serializer.serialize(&value);
+                assert_eq!(deserialize(&serialized).unwrap(), value, "{}", serialized);
+            }
+        }
+    }
+
+    #[test]
+    fn control_characters_roundtrip() {
+        for (s, expected) in [
+            ("\u{0}", "~'*00"),
+            ("\t", "~'*09"),
+            ("\u{1f}", "~'*1f"),
+            ("a\u{0}b\u{0}", "~'a*00b*00"),
+            ("\r\n\u{7f}", "~'*0d*0a*7f"),
+        ] {
+            let value = serde_json::json!(s);
+            assert_eq!(serialize(&value), expected);
+            assert_eq!(deserialize(expected).unwrap(), value);
+            // readable mode must not leave them unescaped either
+            assert_eq!(Serializer::new().readable(true).serialize(&value), expected);
+        }
+        let value = serde_json::json!({"\u{0}": ["\u{0}"]});
+        assert_eq!(serialize(&value), "~(*00~(~'*00))");
+        assert_eq!(deserialize("~(*00~(~'*00))").unwrap(), value);
+    }
+
+    #[test]
+    fn decode_borrows_plain_strings() {
+        let config = Deserializer::new();
+        let mut chars = Reader::new("plain.key-1~rest", &config);
+        assert!(matches!(
+            decode(&mut chars).unwrap(),
+            Cow::Borrowed("plain.key-1")
+        ));
+        assert_eq!(chars.rest(), "~rest");
+
+        let mut chars = Reader::new("a*20b!c)", &config);
+        assert!(matches!(decode(&mut chars).unwrap(), Cow::Owned(s) if s == "a b$c"));
+        assert_eq!(chars.rest(), ")");
+
+        let mut chars = Reader::new("", &config);
+        assert!(matches!(decode(&mut chars).unwrap(), Cow::Borrowed("")));
+
+        // escaped strings are allocated once, with room for the whole encoded length
+        for input in ["*e9*e9*e9~", "**d83d**de00**d83d**de00)", "!!!!", "abc*2a"] {
+            let mut chars = Reader::new(input, &config);
+            let decoded = decode_escaped(&mut chars).unwrap();
+            let encoded_len = input.len() - chars.rest().len();
+            assert_eq!(decoded.capacity(), encoded_len, "{}", input);
+            assert!(decoded.len() <= encoded_len);
+        }
+    }
+
+    #[test]
+    fn hex_escapes_are_case_insensitive() {
+        for input in ["~'*2a", "~'*2A"] {
+            assert_eq!(deserialize(input).unwrap(), serde_json::json!("*"));
+        }
+        for input in ["~'**d83d**de00", "~'**D83D**DE00", "~'**D83d**dE00"] {
+            assert_eq!(deserialize(input).unwrap(), serde_json::json!("\u{1f600}"));
+        }
+        assert_eq!(
+            deserialize("~(*E9~'*FF)").unwrap(),
+            serde_json::json!({"\u{e9}": "\u{ff}"})
+        );
+
+        // serialization uses lowercase unless asked otherwise
+        let value = serde_json::json!(["*\u{ab}\u{ff}\u{abcd}\u{1f600}", {"\u{fe}": 1}]);
+        let serialized = serialize(&value);
+        assert_eq!(serialized, "~(~'*2a*ab*ff**abcd**d83d**de00~(*fe~1))");
+        assert_eq!(serialized, serialized.to_lowercase());
+        let uppercase = Serializer::new().uppercase_hex(true);
+        let serialized = uppercase.serialize(&value);
+        assert_eq!(serialized, "~(~'*2A*AB*FF**ABCD**D83D**DE00~(*FE~1))");
+        assert_eq!(deserialize(&serialized).unwrap(), value);
+        assert_eq!(uppercase.serialized_len(&value), serialized.len());
+
+        // the fixed escapes of other options follow along
+        let uppercase = uppercase.space_as_plus(true).escape_dollar_as_hex(true);
+        assert_eq!(uppercase.serialize(&serde_json::json!("+ $")), "~'*2B+*24");
+    }
+
+    #[test]
+    fn escape_boundary_code_points() {
+        for (s, expected) in [
+            ("\u{7e}", "~'*7e"),
+            ("\u{7f}", "~'*7f"),
+            ("\u{80}", "~'*80"),
+            ("\u{ff}", "~'*ff"),
+            ("\u{100}", "~'**0100"),
+            ("\u{fff}", "~'**0fff"),
+            ("\u{d7ff}", "~'**d7ff"),
+            ("\u{e000}", "~'**e000"),
+            ("\u{fffd}", "~'**fffd"),
+            ("\u{ffff}", "~'**ffff"),
+            ("\u{10000}", "~'**d800**dc00"),
+            ("\u{10ffff}", "~'**dbff**dfff"),
+        ] {
+            let value = serde_json::json!(s);
+            assert_eq!(serialize(&value), expected, "{:?}", s);
+            assert_eq!(deserialize(expected).unwrap(), value, "{:?}", s);
+            // as a key too
+            let value = serde_json::json!({ s: 1 });
+            let serialized = serialize(&value);
+            assert_eq!(serialized, String::from("~(") + &expected[2..] + "~1)");
+            assert_eq!(deserialize(&serialized).unwrap(), value);
+        }
+        // a `**` escape of a char below U+0100 is also read
+        assert_eq!(
+            deserialize("~'**00ff**007f").unwrap(),
+            serde_json::json!("\u{ff}\u{7f}")
+        );
+    }
+
+    #[test]
+    fn astral_plane_strings() {
+        // emoji and CJK extension B characters are split into UTF-16 surrogate pairs
+        let s1 = serde_json::Value::String("\u{1f600}".to_string());
+        assert_eq!(serialize(&s1), "~'**d83d**de00");
+        assert_eq!(deserialize("~'**d83d**de00").unwrap(), s1);
+
+        let s2 = serde_json::Value::String("a\u{20000}b\u{10ffff}".to_string());
+        assert_eq!(serialize(&s2), "~'a**d840**dc00b**dbff**dfff");
+        assert_eq!(deserialize(serialize(&s2)).unwrap(), s2);
+
+        // lone or mismatched surrogates are rejected
+        for s in ["~'**d83d", "~'**d83dx", "~'**d83d**0041", "~'**de00"] {
+            let err = deserialize(s).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidEscape);
+            assert_eq!(err.position(), 2);
+        }
+    }
+
+    #[test]
+    fn invalid_escape_digit_position() {
+        // the error points at the first char that is not a hex digit
+        for (input, position) in [
+            ("~'*g0", 3),
+            ("~'*0g", 4),
+            ("~'**g000", 4),
+            ("~'**0g00", 5),
+            ("~'**00g0", 6),
+            ("~'**000g", 7),
+            ("~'**d83d**dg00", 11),
+            ("~'**d83d**dc0g", 13),
+            ("~(ab*2x~1)", 6),
+            ("~'\u{e9}\u{e9}*x1", 5),
+            ("~'*\u{e9}1", 3),
+        ] {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(
+                (err.kind(), err.position()),
+                (ErrorKind::InvalidEscape, position),
+                "{}",
+                input
+            );
+            assert_eq!(validate(input).unwrap_err(), err);
+            assert_eq!(deserialize_iterative(input).unwrap_err(), err);
+            let mut parser = PushParser::new();
+            let pushed = parser.feed(input).and_then(|()| parser.finish());
+            assert_eq!(pushed.unwrap_err(), err, "{:?}", input);
+        }
+
+        // well-formed digits of an invalid char still point at the escape
+        let err = deserialize("~'x**dc00").unwrap_err();
+        assert_eq!((err.kind(), err.position()), (ErrorKind::InvalidEscape, 3));
+    }
+
+    #[test]
+    fn deserialize_truncated_escape() {
+        // escapes cut short by the end of input
+        for (input, position) in [
+            ("~'*", 3),
+            ("~'*2", 4),
+            ("~'**", 4),
+            ("~'**20", 6),
+            ("~'**201", 7),
+            ("~'abc*", 6),
+            ("~'**d83d**dc0", 13),
+        ] {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof, "{}", input);
+            assert_eq!(err.position(), position, "{}", input);
+            assert_eq!(validate(input).unwrap_err(), err);
+            assert_eq!(deserialize_iterative(input).unwrap_err(), err);
+        }
+
+        // escapes cut short by the end of their string or key, which is not read as a digit
+        // but reported as the invalid one
+        for (input, position) in [
+            ("~'*~", 3),
+            ("~'*2~", 4),
+            ("~'**20)", 6),
+            ("~(~'**201)", 9),
+            ("~(a*2~1)", 5),
+            ("~'**d83d**dc0)", 13),
+        ] {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidEscape, "{}", input);
+            assert_eq!(err.position(), position, "{}", input);
+            assert_eq!(validate(input).unwrap_err(), err);
+            assert_eq!(deserialize_iterative(input).unwrap_err(), err);
+        }
+    }
+
+    #[test]
+    fn deserialize_lossy_surrogates() {
+        let lossy = Deserializer::new().lossy(true);
+        for (input, expected) in [
+            ("~'**d83d", "\u{fffd}"),
+            ("~'**d83dx", "\u{fffd}x"),
+            ("~'**d83d**0041", "\u{fffd}A"),
+            ("~'**de00", "\u{fffd}"),
+            ("~'**d83d**d83d**de00", "\u{fffd}\u{1f600}"),
+            ("~'**de00**d83d", "\u{fffd}\u{fffd}"),
+        ] {
+            assert!(deserialize(input).is_err(), "{}", input);
+            let expected = serde_json::Value::from(expected);
+            assert_eq!(lossy.deserialize(input).unwrap(), expected, "{}", input);
+            assert_eq!(lossy.deserialize_iterative(input).unwrap(), expected);
+            assert!(lossy.validate(input).is_ok());
+        }
+        assert_eq!(
+            lossy.deserialize("~'**d83d**de00").unwrap(),
+            serde_json::json!("\u{1f600}")
+        );
+        assert_eq!(
+            lossy.deserialize("~(**d800~1)").unwrap(),
+            serde_json::json!({"\u{fffd}": 1})
+        );
+
+        // other malformed escapes are still errors
+        for (input, kind, position) in [
+            ("~'**d83d**zzzz", ErrorKind::InvalidEscape, 10),
+            ("~'**d83d**de0", ErrorKind::UnexpectedEof, 13),
+            ("~'*zz", ErrorKind::InvalidEscape, 3),
+        ] {
+            let err = lossy.deserialize(input).unwrap_err();
+            assert_eq!((err.kind(), err.position()), (kind, position), "{}", input);
+        }
+    }
+
+    #[test]
+    fn deserialize_error() {
+        let err = deserialize("").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(err.position(), 0);
+
+        let err = deserialize("hello world").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedChar('h'));
+        assert_eq!(err.position(), 0);
+
+        let err = deserialize("~").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(err.position(), 1);
+
+        let err = deserialize("~cool").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidLiteral);
+        assert_eq!(err.position(), 1);
+    }
+
+    #[test]
+    fn deserialize_degenerate_inputs() {
+        let cases = [
+            ("", ErrorKind::UnexpectedEof, 0),
+            ("~", ErrorKind::UnexpectedEof, 1),
+            (" ", ErrorKind::UnexpectedChar(' '), 0),
+            ("\n", ErrorKind::UnexpectedChar('\n'), 0),
+            (" ~1", ErrorKind::UnexpectedChar(' '), 0),
+            ("(", ErrorKind::UnexpectedChar('('), 0),
+            (")", ErrorKind::UnexpectedChar(')'), 0),
+            ("'", ErrorKind::UnexpectedChar('\''), 0),
+            ("~~", ErrorKind::UnexpectedChar('~'), 1),
+            ("~)", ErrorKind::UnexpectedChar(')'), 1),
+            ("~ ", ErrorKind::InvalidLiteral, 1),
+            ("~*", ErrorKind::InvalidLiteral, 1),
+            ("~1 ", ErrorKind::InvalidNumber, 1),
+            ("~(", ErrorKind::UnexpectedEof, 2),
+            ("~( ", ErrorKind::UnexpectedEof, 3),
+            ("~(~", ErrorKind::UnexpectedEof, 3),
+            ("~(a", ErrorKind::UnexpectedEof, 3),
+            ("~(a~", ErrorKind::UnexpectedEof, 4),
+            ("~(a~)", ErrorKind::UnexpectedChar(')'), 4),
+            ("~(~1~~2)", ErrorKind::UnexpectedChar('~'), 5),
+            ("~(~))", ErrorKind::TrailingCharacters, 4),
+        ];
+        for (input, kind, position) in cases {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(
+                (err.kind(), err.position()),
+                (kind, position),
+                "{:?}",
+                input
+            );
+            assert_eq!(
+                deserialize_iterative(input).unwrap_err(),
+                err,
+                "{:?}",
+                input
+            );
+            assert_eq!(validate(input).unwrap_err(), err, "{:?}", input);
+            let mut parser = PushParser::new();
+            let pushed = parser.feed(input).and_then(|()| parser.finish());
+            assert_eq!(pushed.unwrap_err(), err, "{:?}", input);
+            let from_events = events(input).find_map(Result::err);
+            assert_eq!(from_events, Some(err), "{:?}", input);
+        }
+
+        // the smallest valid inputs
+        assert_eq!(deserialize("~'").unwrap(), serde_json::json!(""));
+        assert_eq!(deserialize("~()").unwrap(), serde_json::json!({}));
+        assert_eq!(deserialize("~(~)").unwrap(), serde_json::json!([]));
+    }
+
+    #[test]
+    fn deserialize_unbalanced_close() {
+        let cases = [
+            ("~'abc)def", ErrorKind::TrailingCharacters, 5),
+            ("~'x)", ErrorKind::TrailingCharacters, 3),
+            ("~1)", ErrorKind::TrailingCharacters, 2),
+            ("~null)", ErrorKind::TrailingCharacters, 5),
+            ("~(a~1))", ErrorKind::TrailingCharacters, 6),
+            ("~(~1))", ErrorKind::TrailingCharacters, 5),
+            ("~())", ErrorKind::TrailingCharacters, 3),
+            ("~()))", ErrorKind::TrailingCharacters, 3),
+            ("~(a~(~1)))", ErrorKind::TrailingCharacters, 9),
+            ("~(a~1)~)", ErrorKind::TrailingCharacters, 6),
+            ("~(a~))", ErrorKind::UnexpectedChar(')'), 4),
+            ("~(a~(~)~b~)", ErrorKind::UnexpectedChar(')'), 10),
+            ("~(~'a~))", ErrorKind::UnexpectedChar(')'), 6),
+        ];
+        for (input, kind, position) in cases {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(
+                (err.kind(), err.position()),
+                (kind, position),
+                "{:?}",
+                input
+            );
+            assert_eq!(deserialize_iterative(input).unwrap_err(), err);
+            assert_eq!(validate(input).unwrap_err(), err);
+            let mut parser = PushParser::new();
+            let pushed = parser.feed(input).and_then(|()| parser.finish());
+            assert_eq!(pushed.unwrap_err(), err, "{:?}", input);
+            assert_eq!(events(input).find_map(Result::err), Some(err));
+        }
+    }
+
+    #[test]
+    fn error_kind_messages() {
+        let kinds = [
+            ErrorKind::UnexpectedEof,
+            ErrorKind::UnexpectedChar(')'),
+            ErrorKind::InvalidEscape,
+            ErrorKind::InvalidNumber,
+            ErrorKind::InvalidLiteral,
+            ErrorKind::DepthLimitExceeded,
+            ErrorKind::InvalidUtf8,
+            ErrorKind::InvalidPercentEncoding,
+            ErrorKind::DuplicateKey,
+            ErrorKind::TrailingCharacters,
+            ErrorKind::InputTooLong,
+            ErrorKind::NumberTooLong,
+        ];
+        let messages: alloc::collections::BTreeSet<String> =
+            kinds.iter().map(|kind| kind.to_string()).collect();
+        assert_eq!(messages.len(), kinds.len());
+        assert!(messages.iter().all(|m| !m.is_empty()));
+        assert_eq!(ErrorKind::UnexpectedChar(')').to_string(), "unexpected ')'");
+        assert_ne!(
+            SerializeError::UnsupportedNumber.to_string(),
+            SerializeError::Write.to_string()
+        );
+    }
+
+    #[test]
+    fn deserialize_top_level_strings() {
+        // a top-level string ends with the input, since nothing follows it to close it
+        for (input, expected) in [
+            ("~'", ""),
+            ("~'abc", "abc"),
+            ("~'a*20b!", "a b$"),
+            ("~'**d83d**de00", "\u{1f600}"),
+        ] {
+            let expected = serde_json::Value::String(String::from(expected));
+            assert_eq!(serialize(&expected), input);
+            assert_eq!(deserialize(input).unwrap(), expected, "{}", input);
+            assert_eq!(deserialize_iterative(input).unwrap(), expected);
+            assert_eq!(validate(input), Ok(()));
+            let mut parser = PushParser::new();
+            parser.feed(input).unwrap();
+            assert_eq!(parser.finish().unwrap(), expected);
+            let events: Result<Vec<_>, _> = events(input).collect();
+            assert_eq!(
+                events.unwrap(),
+                [Event::String(String::from(expected.as_str().unwrap()))]
+            );
+        }
+
+        // a `~` or `)` still ends it, and is left over
+        for (input, position) in [("~'~", 2), ("~'abc~", 5), ("~')", 2), ("~'abc)", 5)] {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(
+                (err.kind(), err.position()),
+                (ErrorKind::TrailingCharacters, position),
+                "{}",
+                input
+            );
+            assert_eq!(deserialize_iterative(input).unwrap_err(), err);
+        }
+    }
+
+    #[test]
+    fn deserialize_key_without_value() {
+        let cases = [
+            ("~(a)", ErrorKind::UnexpectedChar(')'), 3),
+            ("~(a~)", ErrorKind::UnexpectedChar(')'), 4),
+            ("~(a~b)", ErrorKind::InvalidLiteral, 4),
+            ("~(a~'b~c)", ErrorKind::UnexpectedChar(')'), 8),
+            ("~(a~1~b)", ErrorKind::UnexpectedChar(')'), 7),
+            ("~(a~1~b~)", ErrorKind::UnexpectedChar(')'), 8),
+            ("~(a~(b))", ErrorKind::UnexpectedChar(')'), 6),
+            ("~(a", ErrorKind::UnexpectedEof, 3),
+            ("~(a~", ErrorKind::UnexpectedEof, 4),
+        ];
+        for (input, kind, position) in cases {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(
+                (err.kind(), err.position()),
+                (kind, position),
+                "{:?}",
+                input
+            );
+            assert_eq!(deserialize_iterative(input).unwrap_err(), err);
+            assert_eq!(validate(input).unwrap_err(), err);
+            let mut parser = PushParser::new();
+            let pushed = parser.feed(input).and_then(|()| parser.finish());
+            assert_eq!(pushed.unwrap_err(), err, "{:?}", input);
+            assert_eq!(events(input).find_map(Result::err), Some(err));
+        }
+    }
+
+    #[test]
+    fn deserialize_number_grammar() {
+        for (input, expected) in [
+            ("~1e5", 1e5),
+            ("~1E+2", 1e2),
+            ("~2e-1", 0.2),
+            ("~-0.5", -0.5),
+            ("~0", 0.0),
+            ("~10", 10.0),
+        ] {
+            let value = deserialize(input).unwrap();
+            assert_eq!(value.as_f64(), Some(expected), "{}", input);
+            assert!(validate(input).is_ok());
+        }
+
+        for input in [
+            "~0x10", "~1.2.3", "~01", "~-01", "~-", "~-.5", "~1.", "~1e", "~1e+", "~12abc", "~1_0",
+        ] {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidNumber, "{}", input);
+            assert_eq!(err.position(), 1);
+            assert_eq!(validate(input).unwrap_err(), err);
         }
-        serde_json::Value::Array(a) => {
-            output.push_str("~(");
-            if a.is_empty() {
-                output.push('~');
-            } else {
-                for v in a.iter() {
-                    serialize_helper(v, output);
-                }
-            }
-            output.push(')');
+        // floats out of range are only representable with `arbitrary_precision`
+        if !cfg!(feature = "arbitrary_precision") {
+            let err = deserialize("~1e400").unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidNumber);
         }
-        serde_json::Value::Object(o) => {
-            output.push_str("~(");
-            for (i, (k, v)) in o.iter().enumerate() {
-                if i > 0 {
-                    output.push('~');
-                }
-                encode_string(k, output);
-                serialize_helper(v, output);
-            }
-            output.push(')');
+        // tokens that do not start like a number are not numbers at all
+        for input in ["~+1", "~.5", "~Infinity", "~NaN"] {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidLiteral, "{}", input);
+            assert_eq!(err.position(), 1);
         }
     }
-}
 
-fn encode_string(s: &str, output: &mut String) {
-    for ch in s.chars() {
-        if ch.is_ascii_alphanumeric() || ch == '.' || ch == '_' || ch == '-' {
-            output.push(ch);
-        } else if ch == '$' {
-            output.push('!');
-        } else {
-            let code = ch as u32;
-            if code < 0x100 {
-                output.push_str(&format!("*{:02x}", code));
-            } else {
-                output.push_str(&format!("**{:04x}", code));
-            }
+    #[test]
+    fn deserialize_leading_zeros() {
+        for input in ["~00", "~01", "~007", "~-00", "~-01", "~00.5", "~01e2"] {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidNumber, "{}", input);
+            assert_eq!(err.position(), 1);
+        }
+        for (input, expected) in [("~0", 0.0), ("~-0", -0.0), ("~0.05", 0.05), ("~0e1", 0.0)] {
+            let value = deserialize(input).unwrap();
+            assert_eq!(value.as_f64(), Some(expected), "{}", input);
+        }
+        // negative zero is read, but written back as plain zero like JavaScript does
+        if !cfg!(feature = "arbitrary_precision") {
+            assert_eq!(serialize(&deserialize("~-0").unwrap()), "~0");
         }
     }
-}
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct DeserializeError;
+    #[test]
+    fn parse_cursor() {
+        let mut cursor = cursor("~(a~1)~(~)~'x)");
+        assert_eq!(cursor.parse_value().unwrap(), serde_json::json!({"a": 1}));
+        assert_eq!(cursor.offset(), 6);
+
+        // a clone moves on independently
+        let mut copy = cursor.clone();
+        assert_eq!(copy.parse_value().unwrap(), serde_json::json!([]));
+        assert_eq!(copy.parse_value().unwrap(), serde_json::json!("x"));
+        assert_eq!(copy.remaining(), ")");
+        assert_eq!(cursor.remaining(), "~(~)~'x)");
+        assert_eq!(cursor.peek_char(), Some('~'));
 
-pub fn deserialize(s: &str) -> Result<serde_json::Value, DeserializeError> {
-    let mut chars = s.chars();
-    let result = parse_one(&mut chars)?;
-    if chars.next().is_some() {
-        return Err(DeserializeError);
+        // errors refer to the whole input, and parsing can resume after one
+        let shallow = Deserializer::new().max_depth(1);
+        let mut cursor = shallow.cursor("~(~(~1))&~(~2)");
+        let err = cursor.parse_value().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DepthLimitExceeded);
+        assert_eq!(err.position(), 3);
+        while cursor.next_char().is_some_and(|c| c != '&') {}
+        assert_eq!(cursor.parse_value().unwrap(), serde_json::json!([2]));
+        assert_eq!(cursor.next_char(), None);
+        assert_eq!(
+            cursor.parse_value().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
     }
-    Ok(result)
-}
 
-fn hex_digit_to_value(c: char) -> Option<u32> {
-    match c {
-        '0'..='9' => Some(c as u32 - '0' as u32),
-        'a'..='f' => Some(10 + c as u32 - 'a' as u32),
-        'A'..='F' => Some(10 + c as u32 - 'A' as u32),
-        _ => None,
+    #[test]
+    fn deserialize_iterative_matches_recursive() {
+        let inputs = [
+            "~null",
+            "~-1.5",
+            "~'hello*20world**203c",
+            "~(~)",
+            "~()",
+            "~(~1~2~(~3~(~))~(a~()))",
+            "~(a~(~(~1~2)~(~)~(a1~3))~b~(~)~c~(d~'hello~e~()~f~(~)))",
+            "~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))",
+            "",
+            "~",
+            "~(~1~2",
+            "~(a~1~b)",
+            "~(a~1x)",
+            "~(~1))",
+            "~'*zz",
+            "~cool",
+        ];
+        for input in inputs {
+            assert_eq!(
+                deserialize_iterative(input),
+                deserialize(input),
+                "{}",
+                input
+            );
+        }
+
+        let strict = Deserializer::new().reject_duplicate_keys(true);
+        assert_eq!(
+            strict.deserialize_iterative("~(a~1~a~2)"),
+            strict.deserialize("~(a~1~a~2)")
+        );
+        let shallow = Deserializer::new().max_depth(2);
+        assert_eq!(
+            shallow.deserialize_iterative("~(a~(~(~1)))").unwrap_err(),
+            shallow.deserialize("~(a~(~(~1)))").unwrap_err()
+        );
     }
-}
 
-fn hex2_to_unicode(a: char, b: char) -> Option<char> {
-    let high = hex_digit_to_value(a)?;
-    let low = hex_digit_to_value(b)?;
-    std::char::from_u32((high << 4) | low)
-}
+    #[test]
+    fn deserialize_iterative_deep_nesting() {
+        let depth = 100_000;
+        let input = "~(".repeat(depth) + "~1" + &")".repeat(depth);
+        let mut value = deserialize_iterative(&input).unwrap();
+        // take the value apart one level at a time, since dropping it whole would recurse
+        let mut levels = 0;
+        while let serde_json::Value::Array(mut items) = value {
+            value = items.pop().unwrap();
+            levels += 1;
+        }
+        assert_eq!(levels, depth);
+        assert_eq!(value, serde_json::json!(1));
+    }
 
-fn hex4_to_unicode(a: char, b: char, c: char, d: char) -> Option<char> {
-    let highest = hex_digit_to_value(a)?;
-    let high = hex_digit_to_value(b)?;
-    let low = hex_digit_to_value(c)?;
-    let lowest = hex_digit_to_value(d)?;
-    std::char::from_u32((highest << 12) | (high << 8) | (low << 4) | lowest)
-}
+    /// Feeds `input` to a push parser in chunks of `size` chars.
+    fn push_parse(
+        config: &Deserializer,
+        input: &str,
+        size: usize,
+    ) -> Result<serde_json::Value, DeserializeError> {
+        let mut parser = config.push_parser();
+        let mut rest = input;
+        while !rest.is_empty() {
+            let end = rest.char_indices().nth(size).map_or(rest.len(), |(i, _)| i);
+            parser.feed(&rest[..end])?;
+            rest = &rest[end..];
+        }
+        parser.finish()
+    }
 
-fn decode(chars: &mut std::str::Chars) -> Result<String, DeserializeError> {
-    let mut result = String::new();
-    loop {
-        let c = peek(chars);
-        match c {
-            Some('~') | Some(')') => {
-                return Ok(result);
+    #[test]
+    fn push_parser_matches_deserialize() {
+        let inputs = [
+            "~null",
+            "~-1.5",
+            "~'hello*20world**203c!",
+            "~'h*e9llo*",
+            "~(~)",
+            "~()",
+            "~(~1~2~(~3~(~))~(a~()))",
+            "~(a~(~(~1~2)~(~)~(a1~3))~b~(~)~c~(d~'hello~e~()~f~(~)))",
+            "~(name~'J*f6hn*20D**00f6e~age~42~'pets~(~'cat))",
+            "~(k*e9y~'v*e4l)",
+            "",
+            "~",
+            "~(",
+            "~(~1~2",
+            "~(a~1~b)",
+            "~(a~1x)",
+            "~(~1))",
+            "~1~2",
+            "~'*zz",
+            "~cool",
+        ];
+        let configs = [
+            Deserializer::new(),
+            Deserializer::new().reject_duplicate_keys(true),
+            Deserializer::new().max_depth(2),
+            Deserializer::new().allow_trailing_comma_style(true),
+            Deserializer::new().max_input_len(8),
+        ];
+        for config in &configs {
+            for input in inputs
+                .iter()
+                .chain(&["~(a~1~a~2)", "~(~1~)", "~(a~(~(~1)))"])
+            {
+                for size in 1..=input.len().max(1) {
+                    assert_eq!(
+                        push_parse(config, input, size),
+                        config.deserialize(input),
+                        "{} in chunks of {}",
+                        input,
+                        size
+                    );
+                }
             }
-            Some('*') => {
-                chars.next();
-                match chars.next() {
-                    // case: character with unicode value > 0xff
-                    Some('*') => {
-                        let x1 = chars.next().ok_or(DeserializeError)?;
-                        let x2 = chars.next().ok_or(DeserializeError)?;
-                        let x3 = chars.next().ok_or(DeserializeError)?;
-                        let x4 = chars.next().ok_or(DeserializeError)?;
-
-                        result.push(hex4_to_unicode(x1, x2, x3, x4).ok_or(DeserializeError)?);
-                    }
-                    // case: character with unicode value <= 0xff
-                    Some(c) => {
-                        let x1 = c;
-                        let x2 = chars.next().ok_or(DeserializeError)?;
+        }
+    }
 
-                        result.push(hex2_to_unicode(x1, x2).ok_or(DeserializeError)?);
-                    }
-                    None => {
-                        return Err(DeserializeError);
+    #[test]
+    fn push_parser_drops_parsed_input() {
+        let mut parser = PushParser::new();
+        parser.feed("~(~'first~'sec").unwrap();
+        assert_eq!(parser.buf, "~'sec");
+        parser.feed("ond~(~1~2").unwrap();
+        assert_eq!(parser.buf, "~1~2");
+        parser.feed(")~3)").unwrap();
+        assert_eq!(parser.buf, ")~3)");
+        assert_eq!(
+            parser.finish().unwrap(),
+            serde_json::json!(["first", "second", [1, 2], 3])
+        );
+
+        let mut parser = PushParser::new();
+        parser.feed("~(~1~x").unwrap();
+        let err = parser.feed("~2)~3").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidLiteral);
+        assert_eq!(err.position(), 5);
+        assert_eq!(parser.feed("").unwrap_err(), err);
+        assert_eq!(parser.finish().unwrap_err(), err);
+    }
+
+    /// Rebuilds a value from its events, to compare the event parser against the tree parser.
+    fn value_from_events(
+        first: Event,
+        events: &mut Events,
+    ) -> Result<serde_json::Value, DeserializeError> {
+        fn next(events: &mut Events) -> Result<Event, DeserializeError> {
+            events.next().expect("missing event")
+        }
+        Ok(match first {
+            Event::BeginArray => {
+                let mut items = Vec::new();
+                loop {
+                    match next(events)? {
+                        Event::End => break serde_json::Value::Array(items),
+                        event => items.push(value_from_events(event, events)?),
                     }
                 }
             }
-            Some('!') => {
-                result.push('$');
-                chars.next();
+            Event::BeginObject => {
+                let mut map = serde_json::Map::new();
+                loop {
+                    match next(events)? {
+                        Event::End => break serde_json::Value::Object(map),
+                        Event::Key(key) => {
+                            let value = value_from_events(next(events)?, events)?;
+                            map.insert(key, value);
+                        }
+                        other => panic!("unexpected event {:?}", other),
+                    }
+                }
             }
-            Some(c) => {
-                result.push(c);
-                chars.next();
+            Event::String(s) => serde_json::Value::String(s),
+            Event::Number(n) => serde_json::Value::Number(n),
+            Event::Bool(b) => serde_json::Value::Bool(b),
+            Event::Null => serde_json::Value::Null,
+            other => panic!("unexpected event {:?}", other),
+        })
+    }
+
+    #[test]
+    fn events_match_tree_parser() {
+        for input in [
+            "~null",
+            "~'hello*20world**d83d**de00",
+            "~(~)",
+            "~(~(~)~(~1~(~))~'x)",
+            "~(a~1~b~(~true~false)~c~(d~(~))~e~(~(f~-2.5)))",
+            "~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))",
+        ] {
+            let expected = deserialize(input).unwrap();
+            let mut events = events(input);
+            let first = events.next().unwrap().unwrap();
+            assert_eq!(
+                value_from_events(first, &mut events).unwrap(),
+                expected,
+                "{}",
+                input
+            );
+            assert!(events.next().is_none(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn events_span() {
+        let input = "~(k~(~-1.5~(~)~'a*20b~'caf\u{e9})~~null)";
+        let mut events = events(input);
+        let mut spans = Vec::new();
+        while let Some(event) = events.next() {
+            spans.push(&input[events.span()]);
+            if let Event::String(s) = event.unwrap() {
+                let raw = &input.as_bytes()[events.span()];
+                // escape-free strings are their own UTF-8
+                assert_eq!(raw == s.as_bytes(), s != "a b");
             }
-            None => return Ok(result),
         }
+        assert_eq!(
+            spans,
+            [
+                "(",
+                "k",
+                "(",
+                "-1.5",
+                "(",
+                "~)",
+                "a*20b",
+                "caf\u{e9}",
+                ")",
+                "",
+                "null",
+                ")"
+            ]
+        );
     }
-}
 
-fn peek(chars: &std::str::Chars) -> Option<char> {
-    let mut iter = chars.clone();
-    iter.next()
-}
+    #[test]
+    fn events_errors() {
+        let last = |d: &Deserializer, s: &str| d.events(s).find_map(Result::err);
+        let d = Deserializer::new();
+        assert_eq!(
+            last(&d, "~(~1"),
+            Some(DeserializeError::new(ErrorKind::UnexpectedEof, 4))
+        );
+        assert_eq!(
+            last(&d, "~null~").map(|e| e.kind()),
+            Some(ErrorKind::TrailingCharacters)
+        );
+        assert_eq!(
+            last(&d, "~(a~1b)").map(|e| e.kind()),
+            Some(ErrorKind::InvalidNumber)
+        );
+        assert_eq!(
+            last(&d.clone().reject_duplicate_keys(true), "~(a~1~b~(a~2)~a~3)"),
+            Some(DeserializeError::new(ErrorKind::DuplicateKey, 14))
+        );
+        assert_eq!(
+            last(&d.clone().max_depth(2), "~(~(~(~)))").map(|e| e.kind()),
+            Some(ErrorKind::DepthLimitExceeded)
+        );
 
-fn peekn(chars: &std::str::Chars, n: usize) -> Option<char> {
-    let mut iter = chars.clone();
-    for _ in 0..n {
-        iter.next();
+        // the iterator is fused after an error
+        let mut events = events("~(~x)");
+        assert_eq!(events.next(), Some(Ok(Event::BeginArray)));
+        assert!(matches!(events.next(), Some(Err(_))));
+        assert_eq!(events.next(), None);
     }
-    iter.next()
-}
 
-fn eat(chars: &mut std::str::Chars, expected: char) -> Result<(), DeserializeError> {
-    match chars.next() {
-        Some(c) if c == expected => Ok(()),
-        _ => Err(DeserializeError),
+    #[test]
+    fn deserialize_trailing_separators() {
+        let tolerant = Deserializer::new().allow_trailing_comma_style(true);
+        for (input, expected) in [
+            ("~(a~1~)", serde_json::json!({"a": 1})),
+            ("~(~1~2~)", serde_json::json!([1, 2])),
+            (
+                "~(a~(~'x~)~b~(c~null~)~)",
+                serde_json::json!({"a": ["x"], "b": {"c": null}}),
+            ),
+            ("~(~)", serde_json::json!([])),
+            ("~(a~1)", serde_json::json!({"a": 1})),
+        ] {
+            assert_eq!(tolerant.deserialize(input).unwrap(), expected, "{}", input);
+            assert_eq!(
+                tolerant.deserialize_iterative(input).unwrap(),
+                expected,
+                "{}",
+                input
+            );
+            let mut events = tolerant.events(input);
+            let first = events.next().unwrap().unwrap();
+            assert_eq!(value_from_events(first, &mut events).unwrap(), expected);
+        }
+        for input in ["~(a~1~)", "~(~1~2~)"] {
+            assert!(deserialize(input).is_err(), "{}", input);
+            assert!(deserialize_iterative(input).is_err(), "{}", input);
+        }
+        for input in ["~(a~1~~b~2)", "~(~1~~2)", "~(a~1~~)", "~(~1~~)", "~(~~)"] {
+            assert!(tolerant.deserialize(input).is_err(), "{}", input);
+            assert!(tolerant.deserialize_iterative(input).is_err(), "{}", input);
+        }
     }
-}
 
-fn parse_array(chars: &mut std::str::Chars) -> Result<serde_json::Value, DeserializeError> {
-    // handle case where empty array is represented as "~(~)"
-    if let Some(')') = peekn(chars, 1) {
-        eat(chars, '~')?;
-        eat(chars, ')')?;
-        return Ok(serde_json::Value::Array(Vec::new()));
+    #[test]
+    fn nested_empty_arrays() {
+        for (input, expected) in [
+            ("~(a~(~))", serde_json::json!({"a": []})),
+            ("~(~(~)~(~))", serde_json::json!([[], []])),
+            ("~(~(~(~(~))))", serde_json::json!([[[[]]]])),
+            ("~(~1~(~)~2)", serde_json::json!([1, [], 2])),
+            (
+                "~(a~(~)~b~(~(~)~(~))~c~(d~(~)))",
+                serde_json::json!({"a": [], "b": [[], []], "c": {"d": []}}),
+            ),
+            ("~(~(a~(~))~(~(~)))", serde_json::json!([{"a": []}, [[]]])),
+        ] {
+            assert_eq!(deserialize(input).unwrap(), expected, "{}", input);
+            assert_eq!(deserialize_iterative(input).unwrap(), expected, "{}", input);
+            assert_eq!(validate(input), Ok(()), "{}", input);
+            assert_eq!(serialize(&expected), input);
+        }
     }
-    let mut result = Vec::new();
-    loop {
-        if let Some(')') = peek(chars) {
-            chars.next();
-            return Ok(serde_json::Value::Array(result));
+
+    #[test]
+    fn keys_that_look_like_values() {
+        for key in ["null", "true", "false", "42", "-1", "1.5e3"] {
+            let value = serde_json::json!({ key: 1, "x": key });
+            let serialized = serialize(&value);
+            assert_eq!(deserialize(&serialized).unwrap(), value, "{}", serialized);
+            assert_eq!(deserialize_iterative(&serialized).unwrap(), value);
         }
-        result.push(parse_one(chars)?);
+        assert_eq!(
+            deserialize("~(true~1~null~false~42~-1)").unwrap(),
+            serde_json::json!({"true": 1, "null": false, "42": -1})
+        );
+        assert_eq!(
+            deserialize("~(-1~(~)~1~())").unwrap(),
+            serde_json::json!({"-1": [], "1": {}})
+        );
     }
-}
 
-fn parse_object(chars: &mut std::str::Chars) -> Result<serde_json::Value, DeserializeError> {
-    let mut map = serde_json::Map::new();
-    while let Some(c) = peek(chars) {
-        if c == '~' || c == ')' {
-            chars.next();
+    #[test]
+    fn empty_array_and_object_disambiguation() {
+        for (input, expected) in [
+            ("~(~)", serde_json::json!([])),
+            ("~()", serde_json::json!({})),
+            ("~(~(~))", serde_json::json!([[]])),
+            ("~(~()~(~))", serde_json::json!([{}, []])),
+            ("~(a~(~)~b~())", serde_json::json!({"a": [], "b": {}})),
+            ("~(~null)", serde_json::json!([null])),
+        ] {
+            assert_eq!(deserialize(input).unwrap(), expected, "{}", input);
+            assert_eq!(deserialize_iterative(input).unwrap(), expected, "{}", input);
+            assert_eq!(serialize(&expected), input);
         }
-        if c == ')' {
-            break;
+
+        // like the reference implementation, a `~` before `)` is not a valid element, unless
+        // trailing separators are explicitly allowed
+        for input in ["~(~(~)~)", "~(~null~)"] {
+            assert!(deserialize(input).is_err(), "{}", input);
+            assert!(deserialize_iterative(input).is_err(), "{}", input);
         }
-        let key = decode(chars)?;
-        let value = parse_one(chars)?;
-        map.insert(key, value);
-        if peek(chars).map_or(false, |c| c != '~' && c != ')') {
-            return Err(DeserializeError);
+        let tolerant = Deserializer::new().allow_trailing_comma_style(true);
+        assert_eq!(
+            tolerant.deserialize("~(~null~)").unwrap(),
+            serde_json::json!([null])
+        );
+        assert_eq!(
+            tolerant.deserialize("~(~(~)~)").unwrap(),
+            serde_json::json!([[]])
+        );
+
+        // truncated containers
+        for input in [
+            "~(", "~(~", "~(~(~)", "~(a", "~(a~1", "~(a~1~", "~(a~()", "~(~()",
+        ] {
+            let err = deserialize(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof, "{}", input);
+            assert_eq!(err.position(), input.len(), "{}", input);
+            assert_eq!(deserialize_iterative(input).unwrap_err(), err, "{}", input);
+            assert_eq!(events(input).find_map(Result::err), Some(err), "{}", input);
         }
     }
-    Ok(serde_json::Value::Object(map))
-}
 
-fn parse_one(chars: &mut std::str::Chars) -> Result<serde_json::Value, DeserializeError> {
-    eat(chars, '~')?;
-    match chars.next() {
-        Some('(') => {
-            if let Some('~') = peek(chars) {
-                parse_array(chars)
-            } else {
-                parse_object(chars)
-            }
+    #[test]
+    fn validate_matches_deserialize() {
+        for input in [
+            "~null",
+            "~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))",
+            "~(~)",
+            "~()",
+            "~(~(~))",
+            "~(a~(~)~b~())",
+            "~'**d83d**de00",
+            "",
+            "~",
+            "~(",
+            "~(a~1",
+            "~(a~1~)",
+            "~(~null~)",
+            "~(a~1b)",
+            "~'x*zz",
+            "~'**d83d",
+            "~nul",
+            "~1~",
+            "~(a~1)x",
+            "~(a~1~a~2)",
+        ] {
+            assert_eq!(validate(input), deserialize(input).map(drop), "{:?}", input);
         }
-        Some('\'') => Ok(serde_json::Value::String(decode(chars)?)),
-        Some(c) => {
-            let mut result = String::new();
-            result.push(c);
-            loop {
-                match peek(chars) {
-                    Some(')') | Some('~') | None => {
-                        match result.as_str() {
-                            "null" => return Ok(serde_json::Value::Null),
-                            "true" => return Ok(serde_json::Value::Bool(true)),
-                            "false" => return Ok(serde_json::Value::Bool(false)),
-                            _ => {}
-                        }
-                        match result.chars().next() {
-                            Some(c) if c == '-' || c.is_ascii_digit() => {
-                                return Ok(serde_json::Value::Number(
-                                    result.parse().map_err(|_| DeserializeError)?,
-                                ));
-                            }
-                            _ => return Err(DeserializeError),
-                        }
-                    }
-                    Some(c) => {
-                        result.push(c);
-                        chars.next();
-                    }
-                }
-            }
+
+        let strict = Deserializer::new().reject_duplicate_keys(true).max_depth(2);
+        for input in ["~(a~1~a~2)", "~(a~(b~1~b~2))", "~(~(~(~)))", "~(a~(~1))"] {
+            assert_eq!(
+                strict.validate(input),
+                strict.deserialize(input).map(drop),
+                "{:?}",
+                input
+            );
         }
-        None => Err(DeserializeError),
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn deserialize_object_entries_in_order() {
+        let entries = deserialize_entries("~(b~1~a~2~c~(z~1~y~2)~a~3)").unwrap();
+        let keys: Vec<&str> = entries.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["b", "a", "c", "a"]);
+        assert_eq!(entries[3].1, serde_json::json!(3));
+        assert_eq!(deserialize_entries("~()").unwrap(), []);
 
-    #[macro_export]
-    macro_rules! assert_serialize_eq {
-        ($json_str:expr, $expected:expr $(,)?) => {
-            let obj: serde_json::Value = serde_json::from_str($json_str).expect("invalid JSON");
-            let serialized = serialize(&obj);
-            assert_eq!(serialized, $expected);
-        };
+        let err = Deserializer::new()
+            .reject_duplicate_keys(true)
+            .deserialize_entries("~(b~1~a~2~a~3)")
+            .unwrap_err();
+        assert_eq!(err, DeserializeError::new(ErrorKind::DuplicateKey, 10));
+
+        for (input, kind) in [
+            ("~(~1)", ErrorKind::UnexpectedChar('~')),
+            ("~(~)", ErrorKind::UnexpectedChar('~')),
+            ("~'a", ErrorKind::UnexpectedChar('\'')),
+            ("~1", ErrorKind::UnexpectedChar('1')),
+            ("~(a~1", ErrorKind::UnexpectedEof),
+            ("~(a~1)~", ErrorKind::TrailingCharacters),
+        ] {
+            assert_eq!(
+                deserialize_entries(input).unwrap_err().kind(),
+                kind,
+                "{}",
+                input
+            );
+        }
     }
 
-    #[macro_export]
-    macro_rules! assert_deserialize_eq {
-        ($expected:expr, $jsurl_str:expr $(,)?) => {
-            let obj: serde_json::Value = serde_json::from_str($expected).expect("invalid JSON");
-            let deserialized = deserialize($jsurl_str).expect("invalid jsurl");
-            assert_eq!(deserialized, obj);
-        };
+    #[cfg(feature = "std")]
+    #[test]
+    fn deserialize_preserves_key_order() {
+        let value = deserialize("~(b~1~a~2~c~(z~1~y~2))").unwrap();
+        let keys: Vec<&String> = value.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["b", "a", "c"]);
+        let keys: Vec<&String> = value["c"].as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["z", "y"]);
     }
 
     #[test]
-    fn serialize_basic_values() {
-        assert_serialize_eq!("null", "~null");
-        assert_serialize_eq!("true", "~true");
-        assert_serialize_eq!("false", "~false");
-        assert_serialize_eq!("0", "~0");
-        assert_serialize_eq!("1", "~1");
-        assert_serialize_eq!("-1.5", "~-1.5");
+    fn deserialize_duplicate_keys() {
+        assert_deserialize_eq!(r#"{"a":2}"#, "~(a~1~a~2)");
 
-        // note: Infinity, -Infinity, and NaN are not valid JSON numbers
-        // so they cannot be represented with serde_json::Value
+        let strict = Deserializer::new().reject_duplicate_keys(true);
+        let err = strict.deserialize("~(a~1~b~(a~1)~a~2)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+        assert_eq!(err.position(), 14);
+        // keys are compared after decoding escapes
+        let err = strict.deserialize("~(!~1~*24~2)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DuplicateKey);
+        assert_eq!(
+            strict.deserialize("~(a~1~b~(a~2))").unwrap(),
+            serde_json::json!({"a": 1, "b": {"a": 2}})
+        );
     }
 
     #[test]
-    fn serialize_strings() {
-        let s1 = serde_json::Value::String("hello world\u{203c}".to_string());
-        assert_eq!(serialize(&s1), "~'hello*20world**203c");
+    fn deserialize_from_bytes() {
+        let input = "~(name~'J\u{f6}rg~tags~(~'a~'b))";
+        assert_eq!(
+            deserialize_bytes(input.as_bytes()).unwrap(),
+            deserialize(input).unwrap()
+        );
 
-        let s2 = serde_json::Value::String(" !\"#$%&'()*+,-./09:;<=>?@AZ[\\]^_`az{|}~".to_string());
-        assert_eq!(serialize(&s2), "~'*20*21*22*23!*25*26*27*28*29*2a*2b*2c-.*2f09*3a*3b*3c*3d*3e*3f*40AZ*5b*5c*5d*5e_*60az*7b*7c*7d*7e");
+        let err = deserialize_bytes(b"~(a~'x\xff)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidUtf8);
+        assert_eq!(err.position(), 6);
 
-        let s3 = serde_json::Value::String("".to_string());
-        assert_eq!(serialize(&s3), "~'");
+        // truncated multi-byte sequence
+        let err = deserialize_bytes(b"~'\xc3\xb6\xc3").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidUtf8);
+        assert_eq!(err.position(), 3);
+
+        let err = deserialize_bytes(b"~(a~").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
     }
 
     #[test]
-    fn serialize_arrays() {
-        assert_serialize_eq!("[]", "~(~)");
-        assert_serialize_eq!("[1,2,3]", "~(~1~2~3)");
-        assert_serialize_eq!(
-            r#"[null, false, 0, "hello world\u203c"]"#,
-            "~(~null~false~0~'hello*20world**203c)"
+    fn deserialize_surrounding_garbage() {
+        for input in [" ~1", "~1 ", "~~1", "~1~", "~(a~1)~", "\n~(a~1)", "?q=~1"] {
+            assert!(deserialize(input).is_err(), "{:?}", input);
+        }
+        let err = deserialize("~(a~1)~").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TrailingCharacters);
+        assert_eq!(err.position(), 6);
+        let err = deserialize("~(a~1)&b=~2").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TrailingCharacters);
+        assert_eq!(err.position(), 6);
+        assert_eq!(
+            err.to_string(),
+            "error at char 6: trailing characters after value"
+        );
+        assert_eq!(
+            deserialize_iterative("~'x)").unwrap_err().kind(),
+            ErrorKind::TrailingCharacters
         );
+        let err = deserialize("~~1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedChar('~'));
+        assert_eq!(err.position(), 1);
     }
 
     #[test]
-    fn serialize_objects() {
-        assert_serialize_eq!("{}", "~()");
-        assert_serialize_eq!(
-            r#"{"c":null,"d":false,"e":0,"f":"hello world\u203c"}"#,
-            "~(c~null~d~false~e~0~f~'hello*20world**203c)"
-        );
-        assert_serialize_eq!(
-            r#"{"a": [[1, 2],[], {}],"b": [],"c": {"d": "hello","e": {},"f": []}}"#,
-            "~(a~(~(~1~2)~(~)~())~b~(~)~c~(d~'hello~e~()~f~(~)))",
+    fn deserialize_lenient_query_prefix() {
+        let expected = serde_json::json!({"a": 1});
+        for input in [
+            "~(a~1)",
+            "q=~(a~1)",
+            "?q=~(a~1)",
+            "?state=~(a~1)",
+            "=~(a~1)",
+        ] {
+            assert_eq!(deserialize_lenient(input).unwrap(), expected, "{:?}", input);
+        }
+        // only a single segment is skipped
+        assert!(deserialize_lenient("a=b=~1").is_err());
+        assert!(deserialize_lenient("?a=~1&b=~2").is_err());
+        // '=' inside the value is left alone
+        assert_eq!(
+            deserialize_lenient("~'a=b").unwrap(),
+            serde_json::json!("a=b")
         );
+        assert!(deserialize_lenient("x&q=~1").is_err());
+
+        let err = deserialize_lenient("?q=~(a~'x*zz)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidEscape);
+        assert_eq!(err.position(), 10);
     }
 
     #[test]
-    fn serialize_example() {
-        assert_serialize_eq!(
-            r#"{"name":"John Doe","age":42,"children":["Mary","Bill"]}"#,
-            "~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))",
+    fn deserialize_query_params() {
+        let params = deserialize_query("a=~(x~1)&&b=~'hello&c=~(~)&").unwrap();
+        assert_eq!(
+            params,
+            [
+                ("a".to_string(), serde_json::json!({"x": 1})),
+                ("b".to_string(), serde_json::json!("hello")),
+                ("c".to_string(), serde_json::json!([])),
+            ]
+        );
+        assert_eq!(deserialize_query("").unwrap(), []);
+        assert_eq!(deserialize_query("?").unwrap(), []);
+
+        // missing and empty values
+        let err = deserialize_query("?a=~1&flag").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(err.key(), Some("flag"));
+        assert_eq!(err.position(), 10);
+        let err = deserialize_query("a=&b=~1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(err.key(), Some("a"));
+        assert_eq!(err.position(), 2);
+
+        let err = deserialize_query("\u{e9}=~1&b=~(a~1)x").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TrailingCharacters);
+        assert_eq!(err.key(), Some("b"));
+        assert_eq!(
+            err.to_string(),
+            "error at char 13 in parameter 'b': trailing characters after value"
         );
+        assert_eq!(deserialize("~(a~1)x").unwrap_err().key(), None);
     }
 
     #[test]
-    fn deserialize_basic_values() {
-        assert_deserialize_eq!("null", "~null");
-        assert_deserialize_eq!("true", "~true");
-        assert_deserialize_eq!("false", "~false");
-        assert_deserialize_eq!("0", "~0");
-        assert_deserialize_eq!("1", "~1");
-        assert_deserialize_eq!("-1.5", "~-1.5");
+    fn deserialize_percent_decoded() {
+        let decoder = Deserializer::new().percent_decode(true);
+        assert_eq!(
+            decoder
+                .deserialize("%7E(a%7E'hello%2A20world%7Eb%7E(%7E1%7e2))")
+                .unwrap(),
+            serde_json::json!({"a": "hello world", "b": [1, 2]})
+        );
+        // an escaped '%' in the jsurl itself is unaffected
+        assert_eq!(
+            decoder.deserialize("~'100*25").unwrap(),
+            serde_json::json!("100%")
+        );
+        assert_eq!(
+            decoder.deserialize("~'100%2A25").unwrap(),
+            serde_json::json!("100%")
+        );
+        // percent-encoded UTF-8
+        assert_eq!(
+            decoder.deserialize("~'J%C3%B6rg").unwrap(),
+            serde_json::json!("J\u{f6}rg")
+        );
+        assert_eq!(
+            decoder.deserialize_iterative("%7E(%7E1)").unwrap(),
+            serde_json::json!([1])
+        );
+
+        // a '%' that does not start an escape means the input was not percent-encoded
+        for (input, position) in [
+            ("~'100%", 5),
+            ("~'100%2", 5),
+            ("~'a%zz", 3),
+            ("~'\u{e9}%g0", 3),
+        ] {
+            let err = decoder.deserialize(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidPercentEncoding, "{}", input);
+            assert_eq!(err.position(), position, "{}", input);
+        }
+        let err = decoder.deserialize("~'%ff").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidUtf8);
+
+        // disabled by default
+        assert!(deserialize("%7E1").is_err());
     }
 
     #[test]
-    fn deserialize_strings() {
-        let s1 = serde_json::Value::String("hello world\u{203c}".to_string());
-        assert_eq!(deserialize("~'hello*20world**203c").unwrap(), s1);
+    fn deserialize_error_position() {
+        let err = deserialize("~(a~1~b~'x*zz)").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidEscape);
+        assert_eq!(err.position(), 11);
 
-        let s2 = serde_json::Value::String(" !\"#$%&'()*+,-./09:;<=>?@AZ[\\]^_`az{|}~".to_string());
-        assert_eq!(deserialize("~'*20*21*22*23!*25*26*27*28*29*2a*2b*2c-.*2f09*3a*3b*3c*3d*3e*3f*40AZ*5b*5c*5d*5e_*60az*7b*7c*7d*7e").unwrap(), s2);
+        // positions count chars, not bytes
+        let err = deserialize("~'\u{e9}\u{e9}~1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TrailingCharacters);
+        assert_eq!(err.position(), 4);
+
+        let err = deserialize("~(a~1~b~(~2~3)x)").unwrap_err();
+        assert_eq!(err.to_string(), "error at char 14: unexpected 'x'");
     }
 
     #[test]
-    fn deserialize_arrays() {
-        assert_deserialize_eq!("[]", "~(~)");
-        assert_deserialize_eq!("[1,2,3]", "~(~1~2~3)");
-        assert_deserialize_eq!(
-            r#"[null, false, 0, "hello world\u203c"]"#,
-            "~(~null~false~0~'hello*20world**203c)"
+    fn deserialize_input_len_limit() {
+        let d = Deserializer::new().max_input_len(10);
+        assert_eq!(
+            d.deserialize("~(a~'bcde)").unwrap(),
+            serde_json::json!({"a": "bcde"})
+        );
+        // the limit counts chars, not bytes
+        assert!(d
+            .deserialize("~'\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}")
+            .is_ok());
+
+        // rejected up front, even though the input is valid
+        let input = String::from("~(") + &"~1".repeat(100_000) + ")";
+        for err in [
+            d.deserialize(&input).unwrap_err(),
+            d.deserialize_iterative(&input).unwrap_err(),
+            d.deserialize_bytes(input.as_bytes()).unwrap_err(),
+            d.deserialize_query(&(String::from("q=") + &input))
+                .unwrap_err(),
+            d.events(&input).next().unwrap().unwrap_err(),
+        ] {
+            assert_eq!(err, DeserializeError::new(ErrorKind::InputTooLong, 10));
+        }
+        assert_eq!(
+            d.deserialize("~'abcdefghi").unwrap_err().to_string(),
+            "error at char 10: input too long"
         );
     }
 
     #[test]
-    fn deserialize_objects() {
-        assert_deserialize_eq!("{}", "~()");
-        assert_deserialize_eq!(
-            r#"{"c":null,"d":false,"e":0,"f":"hello world\u203c"}"#,
-            "~(c~null~d~false~e~0~f~'hello*20world**203c)"
+    fn deserialize_number_len_limit() {
+        let d = Deserializer::new().max_number_len(5);
+        for input in [
+            "~12345",
+            "~-1234",
+            "~1.5e3",
+            "~(~1~22~333)",
+            "~'123456",
+            "~null",
+        ] {
+            assert!(d.deserialize(input).is_ok(), "{}", input);
+        }
+        let long = String::from("~(a~1~b~") + &"9".repeat(1_000_000) + ")";
+        for (input, position) in [
+            ("~123456", 1),
+            ("~-12345", 1),
+            ("~1.5e-3", 1),
+            ("~(~1~123456)", 5),
+            // checked before the grammar
+            ("~12x456", 1),
+            (long.as_str(), 8),
+        ] {
+            let expected = DeserializeError::new(ErrorKind::NumberTooLong, position);
+            assert_eq!(d.deserialize(input).unwrap_err(), expected, "{}", input);
+            assert_eq!(d.deserialize_iterative(input).unwrap_err(), expected);
+            assert_eq!(d.validate(input).unwrap_err(), expected);
+            assert_eq!(d.events(input).find_map(Result::err), Some(expected));
+        }
+        assert_eq!(
+            d.deserialize("~123456").unwrap_err().to_string(),
+            "error at char 1: number too long"
         );
-        assert_deserialize_eq!(
-            r#"{"a": [[1, 2],[], {"a1": 3}],"b": [],"c": {"d": "hello","e": {},"f": []}}"#,
-            "~(a~(~(~1~2)~(~)~(a1~3))~b~(~)~c~(d~'hello~e~()~f~(~)))",
+        // other tokens are not numbers
+        assert_eq!(
+            d.deserialize("~abcdefg").unwrap_err().kind(),
+            ErrorKind::InvalidLiteral
         );
     }
 
     #[test]
-    fn deserialize_example() {
-        assert_deserialize_eq!(
-            r#"{"name":"John Doe","age":42,"children":["Mary","Bill"]}"#,
-            "~(name~'John*20Doe~age~42~children~(~'Mary~'Bill))",
+    fn deserialize_numbers_as_strings() {
+        let raw = Deserializer::new().numbers_as_strings(true);
+        let input = "~(a~(~0~-0~12.50~1E+2~18446744073709551616)~b~'3~c~true~d~null)";
+        let expected = serde_json::json!({
+            "a": ["0", "-0", "12.50", "1E+2", "18446744073709551616"],
+            "b": "3",
+            "c": true,
+            "d": null,
+        });
+        assert_eq!(raw.deserialize(input).unwrap(), expected);
+        assert_eq!(raw.deserialize_iterative(input).unwrap(), expected);
+        assert_ne!(raw.deserialize(input).unwrap(), deserialize(input).unwrap());
+        let mut parser = raw.push_parser();
+        parser.feed(input).unwrap();
+        assert_eq!(parser.finish().unwrap(), expected);
+
+        // the grammar and length limit still apply, but not the range of an `f64`
+        assert_eq!(
+            raw.deserialize("~1e400").unwrap(),
+            serde_json::json!("1e400")
         );
+        for input in ["~01", "~1.", "~-", "~1e"] {
+            let err = raw.deserialize(input).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidNumber, "{}", input);
+        }
+        let err = raw.max_number_len(2).deserialize("~123").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NumberTooLong);
     }
 
     #[test]
-    fn deserialize_error() {
-        assert_eq!(deserialize("").unwrap_err(), DeserializeError);
-        assert_eq!(deserialize("hello world").unwrap_err(), DeserializeError);
-        assert_eq!(deserialize("~").unwrap_err(), DeserializeError);
-        assert_eq!(deserialize("~cool").unwrap_err(), DeserializeError);
+    fn events_numbers_as_strings() {
+        let raw = Deserializer::new().numbers_as_strings(true);
+        let parsed: Result<Vec<_>, _> = raw.events("~(a~1~b~(~-0.50~1e400~null))").collect();
+        assert_eq!(
+            parsed.unwrap(),
+            [
+                Event::BeginObject,
+                Event::Key(String::from("a")),
+                Event::String(String::from("1")),
+                Event::Key(String::from("b")),
+                Event::BeginArray,
+                Event::String(String::from("-0.50")),
+                Event::String(String::from("1e400")),
+                Event::Null,
+                Event::End,
+                Event::End,
+            ]
+        );
+        let err = raw.events("~(~01)").find_map(Result::err).unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidNumber);
+
+        // without the option, a float out of range is still an error
+        #[cfg(not(feature = "arbitrary_precision"))]
+        {
+            let err = events("~1e400").find_map(Result::err).unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidNumber);
+        }
+    }
+
+    #[test]
+    fn deserialize_depth_limit() {
+        let nested = |depth: usize| "~(".repeat(depth) + "~" + &")".repeat(depth);
+
+        assert!(deserialize(nested(DEFAULT_MAX_DEPTH)).is_ok());
+        let err = deserialize(nested(DEFAULT_MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DepthLimitExceeded);
+        assert_eq!(err.position(), DEFAULT_MAX_DEPTH * 2 + 1);
+
+        // would overflow the stack without a limit
+        let err = deserialize(nested(100_000)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DepthLimitExceeded);
+
+        let shallow = Deserializer::new().max_depth(2);
+        assert!(shallow.deserialize("~(a~(~1)~b~(c~2))").is_ok());
+        let err = shallow.deserialize("~(a~(~(~1)))").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DepthLimitExceeded);
+        assert_eq!(shallow.deserialize("~1").unwrap(), serde_json::json!(1));
+        assert!(Deserializer::new().max_depth(0).deserialize("~()").is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn deserialize_error_is_std_error() {
+        fn parse(s: &str) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+            Ok(deserialize(s)?)
+        }
+        let err = parse("~").unwrap_err();
+        assert_eq!(err.to_string(), "error at char 1: unexpected end of input");
     }
 }
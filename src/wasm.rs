@@ -0,0 +1,51 @@
+//! JavaScript bindings, enabled by the `wasm` feature.
+//!
+//! These mirror the API of the original JavaScript library, so that a build of this crate
+//! can replace it in the browser:
+//!
+//! ```ts
+//! export function stringify(value: any): string;
+//! export function parse(s: string): any;
+//! export function tryParse(s: string, def: any): any;
+//! ```
+//!
+//! Values cross the boundary as plain JavaScript objects, arrays, strings, numbers,
+//! booleans and `null`, the same values that `JSON.stringify` and `JSON.parse` work with.
+//! One difference is that `undefined` is written as `null`, even as an object property,
+//! which the JavaScript library would leave out. Values that JSON cannot represent, such
+//! as functions, are rejected. Errors are thrown as JavaScript `Error`s.
+//!
+//! Since wasm-bindgen needs a `cdylib`, build the module with:
+//!
+//! ```sh
+//! cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/jsurl.wasm
+//! ```
+//!
+//! See `examples/wasm.mjs` for how the result is used.
+
+use alloc::string::String;
+use serde::Serialize as _;
+use wasm_bindgen::prelude::*;
+
+/// Serializes a JavaScript value to jsurl, like `JSURL.stringify`.
+#[wasm_bindgen]
+pub fn stringify(value: JsValue) -> Result<String, JsError> {
+    let value: serde_json::Value = serde_wasm_bindgen::from_value(value)?;
+    Ok(crate::serialize(&value))
+}
+
+/// Deserializes a jsurl string to a JavaScript value, like `JSURL.parse`.
+#[wasm_bindgen]
+pub fn parse(s: &str) -> Result<JsValue, JsError> {
+    let value = crate::deserialize(s)?;
+    // plain objects rather than `Map`s, as `JSON.parse` would produce
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+/// Like [`parse`], but returns `def` instead of throwing if `s` is not valid jsurl, like
+/// `JSURL.tryParse`.
+#[wasm_bindgen(js_name = tryParse)]
+pub fn try_parse(s: &str, def: JsValue) -> JsValue {
+    parse(s).unwrap_or(def)
+}